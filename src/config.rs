@@ -0,0 +1,244 @@
+use http::{request::Parts, HeaderValue, StatusCode};
+use std::{fmt, net::IpAddr, sync::Arc};
+use crate::{hooks::SharedPreCreateHook, AuthClaims, CorsConfig, MetadataPolicy, PreCreateHook, UploadEvents, UploadSigner};
+
+/// Size of the chunks PATCH bodies are written in, unless configured otherwise.
+const DEFAULT_PATCH_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Largest `Upload-Length` accepted, unless configured otherwise (300 GB).
+pub(crate) const DEFAULT_MAX_SIZE: u64 = 300_000_000_000;
+
+/// Capacity of the event channel created for [`TusConfig::with_progress`].
+const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+type AuthFn = dyn Fn(&Parts) -> Option<Arc<dyn AuthClaims>> + Send + Sync;
+
+/// Resolves the claims of the caller from every tus request.
+#[derive(Clone)]
+pub(crate) struct AuthHook(Arc<AuthFn>);
+
+impl AuthHook {
+    pub(crate) fn resolve(&self, parts: &Parts) -> Option<Arc<dyn AuthClaims>> {
+        (self.0)(parts)
+    }
+}
+
+impl fmt::Debug for AuthHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuthHook")
+    }
+}
+
+/// Configures the optional behaviour of the tus routes, see [`setup_tus_routes_with_config`](crate::setup_tus_routes_with_config).
+#[derive(Debug, Clone, Default)]
+pub struct TusConfig {
+    pub(crate) download: Option<DownloadConfig>,
+    pub(crate) auth: Option<AuthHook>,
+    pub(crate) signer: Option<UploadSigner>,
+    pub(crate) metadata_policy: Option<MetadataPolicy>,
+    pub(crate) events: Option<UploadEvents>,
+    pub(crate) progress: bool,
+    pub(crate) pre_create: Option<SharedPreCreateHook>,
+    pub(crate) mount_path: Option<String>,
+    pub(crate) trusted_proxies: Vec<IpAddr>,
+    pub(crate) cors: Option<CorsConfig>,
+    pub(crate) ignore_method_override: bool,
+    max_size: Option<u64>,
+    patch_chunk_size: Option<usize>,
+}
+
+impl TusConfig {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Enables `GET /:id` to download completed uploads.
+    pub fn with_download(mut self, download: DownloadConfig) -> Self {
+        self.download = Some(download);
+        self
+    }
+
+    /// Resolves the claims of the caller from each request (e.g. from a header, or from claims a
+    /// previous layer inserted into the extensions).
+    ///
+    /// Requests without claims are rejected with `401 Unauthorized` (except `OPTIONS`). New
+    /// uploads are owned by [`AuthClaims::get_user_id`], and every other request on an upload is
+    /// rejected with `403 Forbidden` unless the caller owns it or [`AuthClaims::is_admin`].
+    pub fn with_auth<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Parts) -> Option<Arc<dyn AuthClaims>> + Send + Sync + 'static,
+    {
+        self.auth = Some(AuthHook(Arc::new(hook)));
+        self
+    }
+
+    /// Accepts HEAD and PATCH requests carrying a valid signature from `signer`, in the query
+    /// string or the [`Upload-Signature`](crate::UPLOAD_SIGNATURE_HEADER) header, without
+    /// resolving the caller through the auth hook.
+    pub fn with_signed_uploads(mut self, signer: UploadSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Rejects creations whose `Upload-Metadata` breaks `policy` with `400 Bad Request`.
+    pub fn with_metadata_policy(mut self, policy: MetadataPolicy) -> Self {
+        self.metadata_policy = Some(policy);
+        self
+    }
+
+    /// Broadcasts an [`UploadEvent`](crate::UploadEvent) to the subscribers of `events` whenever
+    /// an upload is created, patched, completed or terminated.
+    pub fn with_events(mut self, events: UploadEvents) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Enables `GET /:id/progress`, a Server-Sent Events stream of the offset of an upload as
+    /// PATCH data is written. It sends a `progress` event with the current offset, then one after
+    /// every written chunk, and closes with a `completed` or `terminated` event.
+    ///
+    /// Progress is read from the [`UploadEvents`] of [`TusConfig::with_events`], which are
+    /// created if none are configured.
+    pub fn with_progress(mut self) -> Self {
+        self.progress = true;
+        self.events.get_or_insert_with(|| UploadEvents::new(DEFAULT_EVENT_CAPACITY));
+        self
+    }
+
+    /// Asks `hook` before every upload is created, rejecting it with the status and message of
+    /// the [`HookRejection`](crate::HookRejection) it returns.
+    pub fn with_pre_create_hook<H: PreCreateHook + 'static>(mut self, hook: H) -> Self {
+        self.pre_create = Some(SharedPreCreateHook(Arc::new(hook)));
+        self
+    }
+
+    /// PATCH bodies are written to the store in chunks of `size` bytes as they are received,
+    /// reporting progress after each one (1 MiB by default). Chunks are never smaller than
+    /// [`FileStore::min_patch_size`](crate::FileStore::min_patch_size), e.g. the minimum part
+    /// size of an [`ObjectFileStore`](crate::ObjectFileStore).
+    pub fn with_patch_chunk_size(mut self, size: usize) -> Self {
+        self.patch_chunk_size = Some(size);
+        self
+    }
+
+    /// Serves the tus routes under `path` (e.g. `/api/files`) instead of at the root of the
+    /// router passed to [`setup_tus_routes_with_config`](crate::setup_tus_routes_with_config).
+    ///
+    /// The `Location` of new uploads is always built from the URL the client posted to, so it is
+    /// also correct when [`tus_router`](crate::tus_router) is nested by hand.
+    pub fn with_mount_path(mut self, path: &str) -> Self {
+        let path = path.trim_matches('/');
+        self.mount_path = (!path.is_empty()).then(|| format!("/{}", path));
+        self
+    }
+
+    /// Builds the `Location` of new uploads from the `Forwarded` or `X-Forwarded-Proto` and
+    /// `X-Forwarded-Host` headers of requests coming from `proxies`, instead of their `Host`.
+    /// Only the last value of each header, added by the proxy itself, is used.
+    ///
+    /// The peer address is read from `ConnectInfo<SocketAddr>`, so the app has to be served with
+    /// `into_make_service_with_connect_info::<SocketAddr>()`.
+    pub fn with_trusted_proxies<I: IntoIterator<Item = IpAddr>>(mut self, proxies: I) -> Self {
+        self.trusted_proxies.extend(proxies);
+        self
+    }
+
+    /// Answers CORS preflight requests and exposes the tus headers to browser clients, see
+    /// [`CorsConfig`].
+    pub fn with_cors(mut self, cors: CorsConfig) -> Self {
+        self.cors = Some(cors);
+        self
+    }
+
+    /// Stops treating POST requests with an `X-HTTP-Method-Override` header naming PATCH or
+    /// DELETE as that method. By default clients which can't send PATCH or DELETE (e.g. behind
+    /// restrictive proxies) can send a POST with the header instead, as the tus protocol
+    /// recommends. Other methods in the header are ignored.
+    pub fn without_method_override(mut self) -> Self {
+        self.ignore_method_override = true;
+        self
+    }
+
+    /// Rejects creations with an `Upload-Length` above `max_size` bytes with
+    /// `413 Payload Too Large`, and advertises it as `Tus-Max-Size` (300 GB by default).
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub(crate) fn max_size(&self) -> u64 {
+        self.max_size.unwrap_or(DEFAULT_MAX_SIZE)
+    }
+
+    pub(crate) fn patch_chunk_size(&self) -> usize {
+        self.patch_chunk_size.unwrap_or(DEFAULT_PATCH_CHUNK_SIZE)
+    }
+}
+
+/// The `filetype`s served as they are by default, which browsers don't run as a page or script.
+const DEFAULT_DOWNLOAD_CONTENT_TYPES: [&str; 13] = [
+    "application/pdf",
+    "application/zip",
+    "audio/mpeg",
+    "audio/ogg",
+    "audio/wav",
+    "image/gif",
+    "image/jpeg",
+    "image/png",
+    "image/webp",
+    "text/csv",
+    "text/plain",
+    "video/mp4",
+    "video/webm",
+];
+
+/// Configures the download extension (`GET /:id`).
+///
+/// Downloads are always sent as attachments with `X-Content-Type-Options: nosniff`, and the
+/// client supplied `filetype` is only used as `Content-Type` when it is allowed (otherwise
+/// `application/octet-stream`), so uploads can't be served as HTML or scripts.
+#[derive(Debug, Clone)]
+pub struct DownloadConfig {
+    pub(crate) incomplete_status: StatusCode,
+    pub(crate) allowed_content_types: Vec<String>,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            incomplete_status: StatusCode::NOT_FOUND,
+            allowed_content_types: DEFAULT_DOWNLOAD_CONTENT_TYPES.iter().map(|content_type| content_type.to_string()).collect(),
+        }
+    }
+}
+
+impl DownloadConfig {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The status returned when downloading an upload that is not completed yet:
+    /// `404 Not Found` by default, or e.g. `409 Conflict`.
+    pub fn with_incomplete_status(mut self, status: StatusCode) -> Self {
+        self.incomplete_status = status;
+        self
+    }
+
+    /// Replaces the `filetype`s served as `Content-Type`, e.g. `["image/png", "image/jpeg"]`.
+    pub fn with_allowed_content_types(mut self, content_types: &[&str]) -> Self {
+        self.allowed_content_types = content_types.iter().map(|content_type| content_type.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// The `Content-Type` of an upload whose metadata has `filetype`.
+    pub(crate) fn content_type(&self, filetype: Option<&str>) -> HeaderValue {
+        let allowed = filetype.filter(|filetype| {
+            let essence = filetype.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+            self.allowed_content_types.contains(&essence)
+        });
+
+        allowed
+            .and_then(|filetype| HeaderValue::from_str(filetype).ok())
+            .unwrap_or_else(|| HeaderValue::from_static("application/octet-stream"))
+    }
+}
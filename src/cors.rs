@@ -0,0 +1,214 @@
+use axum::response::IntoResponse;
+use http::{header, request::Parts, HeaderMap, HeaderValue, Method, Response, StatusCode};
+use std::time::Duration;
+
+use crate::tus_service::BoxBody;
+
+const ALLOWED_METHODS: &str = "POST, GET, HEAD, PATCH, DELETE, OPTIONS";
+
+/// The headers tus clients send.
+const ALLOWED_HEADERS: [&str; 13] = [
+    "Authorization",
+    "Origin",
+    "X-Requested-With",
+    "X-Request-ID",
+    "X-HTTP-Method-Override",
+    "Content-Type",
+    "Upload-Length",
+    "Upload-Offset",
+    "Tus-Resumable",
+    "Upload-Metadata",
+    "Upload-Defer-Length",
+    "Upload-Concat",
+    "Upload-Signature",
+];
+
+/// The headers tus clients have to read from responses.
+const EXPOSED_HEADERS: &str = "Upload-Offset, Location, Upload-Length, Tus-Version, Tus-Resumable, Tus-Max-Size, \
+    Tus-Extension, Upload-Metadata, Upload-Defer-Length, Upload-Concat, Upload-Expires";
+
+/// Configures CORS for browser tus clients (e.g. tus-js-client or Uppy), see
+/// [`TusConfig::with_cors`](crate::TusConfig::with_cors).
+///
+/// Preflight requests (`OPTIONS` with `Origin` and `Access-Control-Request-Method`) are answered
+/// before the tus routes, and any other `OPTIONS` request still reaches tus discovery. Responses
+/// to allowed origins expose the tus headers, such as `Upload-Offset` and `Location`.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    allowed_origins: Option<Vec<HeaderValue>>,
+    allowed_headers: String,
+    allow_credentials: bool,
+    max_age: Duration,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: None,
+            allowed_headers: ALLOWED_HEADERS.join(", "),
+            allow_credentials: false,
+            max_age: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Allows every origin.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only allows `origins`, e.g. `["https://app.example.com"]`. Preflight requests from any
+    /// other origin are rejected with `403 Forbidden`. Panics if an origin isn't a valid header
+    /// value.
+    pub fn with_allowed_origins(mut self, origins: &[&str]) -> Self {
+        let origins = origins
+            .iter()
+            .map(|origin| HeaderValue::from_str(origin).unwrap_or_else(|_| panic!("invalid CORS origin {:?}", origin)));
+        self.allowed_origins.get_or_insert_with(Vec::new).extend(origins);
+        self
+    }
+
+    /// Also allows `headers` in requests, next to the tus headers and `Authorization`.
+    pub fn with_allowed_headers(mut self, headers: &[&str]) -> Self {
+        for name in headers {
+            self.allowed_headers.push_str(", ");
+            self.allowed_headers.push_str(name);
+        }
+        self
+    }
+
+    /// Lets browsers send cookies and credentials with requests.
+    pub fn with_credentials(mut self) -> Self {
+        self.allow_credentials = true;
+        self
+    }
+
+    /// How long browsers may cache preflight responses (a day by default).
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub(crate) fn is_preflight(parts: &Parts) -> bool {
+        parts.method == Method::OPTIONS
+            && parts.headers.contains_key(header::ORIGIN)
+            && parts.headers.contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+    }
+
+    pub(crate) fn preflight(&self, parts: &Parts) -> Response<BoxBody> {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+
+        if !self.apply_origin(parts.headers.get(header::ORIGIN), response.headers_mut()) {
+            *response.status_mut() = StatusCode::FORBIDDEN;
+            return response;
+        }
+
+        let headers = response.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static(ALLOWED_METHODS));
+        if let Ok(allowed_headers) = HeaderValue::from_str(&self.allowed_headers) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers);
+        }
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(self.max_age.as_secs()));
+
+        response
+    }
+
+    /// Adds the CORS headers of a (non preflight) response to a request from `origin`.
+    pub(crate) fn apply(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        if self.apply_origin(origin, headers) {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, HeaderValue::from_static(EXPOSED_HEADERS));
+        }
+    }
+
+    /// Allows `origin` in `headers`, returning whether it is allowed.
+    fn apply_origin(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) -> bool {
+        let Some(origin) = origin else {
+            return false;
+        };
+
+        let allowed = match &self.allowed_origins {
+            Some(origins) => origins.contains(origin),
+            None => true,
+        };
+        if !allowed {
+            return false;
+        }
+
+        // `*` can't be used with credentials, and responses differ per origin with a list
+        if self.allowed_origins.is_none() && !self.allow_credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+        } else {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+
+        if self.allow_credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::Request;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_util::{creation_request, memory_store, tus_app};
+    use crate::TusConfig;
+
+    fn preflight(uri: &str, origin: &str) -> Request<Body> {
+        Request::options(uri)
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "PATCH")
+            .header("Access-Control-Request-Headers", "tus-resumable, upload-offset, content-type")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cors_next_to_discovery() {
+        let cors = CorsConfig::new().with_allowed_origins(&["https://app.example.com"]);
+        let app = tus_app(memory_store(), TusConfig::new().with_cors(cors));
+
+        let response = app.clone().oneshot(preflight("/some-upload", "https://app.example.com")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["Access-Control-Allow-Origin"], "https://app.example.com");
+        assert!(response.headers()["Access-Control-Allow-Methods"].to_str().unwrap().contains("PATCH"));
+        assert!(response.headers()["Access-Control-Allow-Headers"].to_str().unwrap().contains("Upload-Offset"));
+
+        let response = app.clone().oneshot(preflight("/", "https://evil.example.com")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!response.headers().contains_key("Access-Control-Allow-Origin"));
+
+        // discovery still reaches the tus routes
+        let request = Request::options("/")
+            .header("Origin", "https://app.example.com")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["Tus-Version"], "1.0.0");
+        assert!(!response.headers().contains_key("Access-Control-Allow-Methods"));
+
+        let request = creation_request(3)
+            .header("Origin", "https://app.example.com")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()["Access-Control-Allow-Origin"], "https://app.example.com");
+        let exposed = response.headers()["Access-Control-Expose-Headers"].to_str().unwrap();
+        assert!(exposed.contains("Location") && exposed.contains("Upload-Offset") && exposed.contains("Upload-Expires"));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid CORS origin")]
+    fn test_invalid_origin() {
+        let _ = CorsConfig::new().with_allowed_origins(&["https://app.example.com\n"]);
+    }
+}
@@ -0,0 +1,158 @@
+use http::{HeaderMap, Method, Uri};
+use tokio::sync::broadcast;
+
+use crate::{
+    filesystem::{
+        file_info::{Created, FileInfo},
+        file_store::{FileStore, FileStoreError},
+    },
+    RequestClaims,
+};
+
+/// What happened to an upload, see [`UploadEvent`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadEventKind {
+    Created,
+    /// Bytes were written, the upload is now at this offset.
+    Progress(u64),
+    Completed,
+    Terminated,
+    /// The upload was removed by [`UploadEvents::remove_expired_files`].
+    Expired,
+    /// Creating, patching or terminating the upload failed with this error.
+    Failed(String),
+}
+
+impl UploadEventKind {
+    /// The name of the event, used as the `type` of [`HookPayload`](crate::HookPayload)s.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Progress(_) => "progress",
+            Self::Completed => "completed",
+            Self::Terminated => "terminated",
+            Self::Expired => "expired",
+            Self::Failed(_) => "failed",
+        }
+    }
+}
+
+/// The request that caused an [`UploadEvent`].
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub user_id: Option<String>,
+    pub tenant_id: Option<String>,
+}
+
+impl RequestContext {
+    pub(crate) fn new(method: Method, uri: Uri, headers: HeaderMap, claims: Option<&RequestClaims>) -> Self {
+        Self {
+            method,
+            uri,
+            headers,
+            user_id: claims.map(|claims| claims.0.get_user_id().to_string()),
+            tenant_id: claims.and_then(|claims| claims.0.get_tenant_id().map(str::to_string)),
+        }
+    }
+}
+
+/// An upload lifecycle event, with a snapshot of the upload taken when it happened.
+#[derive(Debug, Clone)]
+pub struct UploadEvent {
+    pub kind: UploadEventKind,
+    pub file_info: FileInfo<Created>,
+    /// `None` for events not caused by a request, such as [`UploadEventKind::Expired`].
+    pub context: Option<RequestContext>,
+}
+
+/// Broadcasts [`UploadEvent`]s to every subscriber, see
+/// [`TusConfig::with_events`](crate::TusConfig::with_events).
+///
+/// Subscribers lagging more than `capacity` events behind miss the oldest ones, see
+/// [`broadcast::error::RecvError::Lagged`].
+#[derive(Debug, Clone)]
+pub struct UploadEvents {
+    sender: broadcast::Sender<UploadEvent>,
+}
+
+impl UploadEvents {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<UploadEvent> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn emit(&self, kind: UploadEventKind, file_info: FileInfo<Created>, context: Option<&RequestContext>) {
+        // sending only fails when nobody is subscribed
+        let _ = self.sender.send(UploadEvent { kind, file_info, context: context.cloned() });
+    }
+
+    /// Like [`FileStore::remove_expired_files`], emitting [`UploadEventKind::Expired`] for every
+    /// removed upload.
+    pub async fn remove_expired_files<T: FileStore>(&self, file_store: &T) -> Result<Vec<FileInfo<Created>>, FileStoreError> {
+        let removed = file_store.remove_expired_files().await?;
+
+        for file_info in &removed {
+            self.emit(UploadEventKind::Expired, file_info.clone(), None);
+        }
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::filesystem::file_info::unix_timestamp;
+    use crate::test_util::{create_upload, creation_request, memory_store, tus_app, tus_request};
+    use crate::TusConfig;
+
+    #[tokio::test]
+    async fn test_events_are_emitted() {
+        let events = UploadEvents::new(16);
+        let mut receiver = events.subscribe();
+        let app = tus_app(memory_store(), TusConfig::new().with_events(events));
+
+        let response = app.clone().oneshot(creation_request(3).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.kind, UploadEventKind::Created);
+        assert_eq!(event.context.unwrap().method, Method::POST);
+        let id = event.file_info.id().to_string();
+
+        let request = tus_request(Method::DELETE, &format!("/{}", id)).body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.kind, UploadEventKind::Terminated);
+        assert_eq!(event.file_info.id(), id);
+    }
+
+    #[tokio::test]
+    async fn test_expired_events_are_emitted() {
+        let store = memory_store();
+        let events = UploadEvents::new(16);
+        let mut receiver = events.subscribe();
+
+        let file_info = create_upload(&store, 3).await;
+        store.set_expiry(file_info.id(), Some(unix_timestamp() - 1)).await.unwrap();
+        let removed = events.remove_expired_files(&store).await.unwrap();
+        assert_eq!(removed.iter().map(|file_info| file_info.id()).collect::<Vec<_>>(), [file_info.id()]);
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.kind, UploadEventKind::Expired);
+        assert!(event.context.is_none());
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/// Server-side data the application attaches to an upload, e.g. a target folder or a
+/// processing status.
+///
+/// Implementors are stored under [`KEY`](FileInfoExtension::KEY) in [`FileInfoExtensions`].
+pub trait FileInfoExtension: Serialize + DeserializeOwned {
+    const KEY: &'static str;
+}
+
+/// A typed map of [`FileInfoExtension`]s, persisted with the rest of the
+/// [`FileInfo`](crate::filesystem::file_info::FileInfo) but never sent to tus clients.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FileInfoExtensions(BTreeMap<String, Value>);
+
+impl FileInfoExtensions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the extension, `None` when unset and an error when the stored value doesn't
+    /// deserialize into `E`.
+    pub fn get<E: FileInfoExtension>(&self) -> Option<Result<E, serde_json::Error>> {
+        self.0.get(E::KEY).map(|value| E::deserialize(value))
+    }
+
+    /// Sets the extension, replacing any previous value stored under its key.
+    pub fn insert<E: FileInfoExtension>(&mut self, extension: &E) -> Result<(), serde_json::Error> {
+        self.0.insert(E::KEY.to_string(), serde_json::to_value(extension)?);
+        Ok(())
+    }
+
+    pub fn remove<E: FileInfoExtension>(&mut self) -> bool {
+        self.0.remove(E::KEY).is_some()
+    }
+
+    /// The raw value stored under `key`.
+    pub fn get_raw(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Folder(String);
+
+    impl FileInfoExtension for Folder {
+        const KEY: &'static str = "folder";
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Processing {
+        status: String,
+    }
+
+    impl FileInfoExtension for Processing {
+        const KEY: &'static str = "folder";
+    }
+
+    #[test]
+    fn test_extensions() {
+        let mut extensions = FileInfoExtensions::new();
+        assert!(extensions.get::<Folder>().is_none());
+
+        extensions.insert(&Folder("invoices".to_string())).unwrap();
+        assert_eq!(extensions.get::<Folder>().unwrap().unwrap(), Folder("invoices".to_string()));
+        assert!(extensions.get::<Processing>().unwrap().is_err());
+
+        let json = serde_json::to_string(&extensions).unwrap();
+        assert_eq!(json, r#"{"folder":"invoices"}"#);
+        assert_eq!(serde_json::from_str::<FileInfoExtensions>(&json).unwrap(), extensions);
+
+        assert!(extensions.remove::<Folder>());
+        assert!(extensions.is_empty());
+    }
+}
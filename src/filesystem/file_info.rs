@@ -1,26 +1,38 @@
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::filesystem::metadata::Metadata;
+use crate::filesystem::{
+    extensions::FileInfoExtensions,
+    metadata::{Metadata, MetadataError},
+};
 use std::{
     io::{Error, ErrorKind, Result},
     marker::PhantomData,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+/// Seconds since the unix epoch, the unit used for every timestamp we persist.
+pub(crate) fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Building;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Built;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Created;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Completed;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Terminated;
 
 /// A struct representing a file and its metadata during various stages of processing.
@@ -30,13 +42,22 @@ pub struct Terminated;
 /// - [`Created`] - The file information has been saved on disk.
 /// - [`Completed`] - The file has been fully processed and is ready to be used.
 /// - [`Terminated`] - The file has been terminated and is no longer saved on disk.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct FileInfo<State = Building> {
     id: String,
     file_name: String,
     length: u64,
     offset: u64,
     metadata: Option<Metadata>,
+    #[serde(default)]
+    owner: Option<String>,
+    #[serde(default)]
+    tenant: Option<String>,
+    // seconds since the unix epoch
+    #[serde(default)]
+    expires_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "FileInfoExtensions::is_empty")]
+    extensions: FileInfoExtensions,
 
     #[serde(skip)]
     state: PhantomData<State>,
@@ -51,18 +72,63 @@ impl<State> FileInfo<State> {
         &self.length
     }
 
+    pub fn offset(&self) -> &u64 {
+        &self.offset
+    }
+
     pub fn metadata(&self) -> &Option<Metadata> {
         &self.metadata
     }
 
+    /// Deserializes the metadata into `T`, see [`Metadata::deserialize_into`].
+    pub fn metadata_as<T: DeserializeOwned>(&self) -> std::result::Result<T, MetadataError> {
+        match &self.metadata {
+            Some(metadata) => metadata.deserialize_into(),
+            None => Metadata::new().deserialize_into(),
+        }
+    }
+
     pub fn name(&self) -> &String {
         &self.file_name
     }
 
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    pub fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    /// Application data attached to the upload, see [`FileInfoExtensions`].
+    pub fn extensions(&self) -> &FileInfoExtensions {
+        &self.extensions
+    }
+
+    /// A copy of the info, used as the snapshot of [`UploadEvent`](crate::UploadEvent)s.
+    pub(crate) fn snapshot(&self) -> FileInfo<Created> {
+        FileInfo::<Created> {
+            state: std::marker::PhantomData,
+            id: self.id.clone(),
+            length: self.length,
+            offset: self.offset,
+            metadata: self.metadata.clone(),
+            file_name: self.file_name.clone(),
+            owner: self.owner.clone(),
+            tenant: self.tenant.clone(),
+            expires_at: self.expires_at,
+            extensions: self.extensions.clone(),
+        }
+    }
+
     // for use with the Upload-Metadata header
     pub fn metadata_str(&self) -> String {
         if let Some(metadata) = &self.metadata {
-            serde_json::to_string(metadata).unwrap()
+            metadata.encode()
         } else {
             String::default()
         }
@@ -109,11 +175,38 @@ impl FileInfo<Building> {
             offset: self.offset,
             metadata: self.metadata,
             file_name: self.file_name,
+            owner: self.owner,
+            tenant: self.tenant,
+            expires_at: self.expires_at,
+            extensions: self.extensions,
         }
     }
 }
 
 impl FileInfo<Built> {
+    /// Records the user creating the upload, see [`AuthClaims`](crate::AuthClaims).
+    pub(crate) fn with_owner(mut self, owner: &str) -> Self {
+        self.owner = Some(owner.to_string());
+        self
+    }
+
+    pub(crate) fn with_tenant(mut self, tenant: Option<&str>) -> Self {
+        self.tenant = tenant.map(str::to_string);
+        self
+    }
+
+    /// Prefixes the id with the key of the store the upload is created in, see
+    /// [`StoreResolver`](crate::StoreResolver).
+    pub(crate) fn with_store_key(mut self, key: &str) -> Self {
+        self.id = format!("{}{}{}", key, crate::STORE_KEY_SEPARATOR, self.id);
+        self
+    }
+
+    /// Lets the application attach data before the upload is created.
+    pub fn extensions_mut(&mut self) -> &mut FileInfoExtensions {
+        &mut self.extensions
+    }
+
     pub(super) fn mark_as_created(self, file_name: &str) -> FileInfo<Created> {
         FileInfo::<Created> {
             file_name: file_name.to_string(),
@@ -122,16 +215,24 @@ impl FileInfo<Built> {
             length: self.length,
             offset: self.offset,
             metadata: self.metadata,
+            owner: self.owner,
+            tenant: self.tenant,
+            expires_at: self.expires_at,
+            extensions: self.extensions,
         }
     }
 }
 
 impl FileInfo<Created> {
-    pub fn offset(&self) -> &u64 {
-        &self.offset
+    pub(super) fn set_expiry(&mut self, expires_at: Option<u64>) {
+        self.expires_at = expires_at;
     }
 
-    pub(super) fn set_offset(&mut self, offset: u64) -> Result<()> {
+    pub(super) fn set_extensions(&mut self, extensions: FileInfoExtensions) {
+        self.extensions = extensions;
+    }
+
+    pub(crate) fn set_offset(&mut self, offset: u64) -> Result<()> {
         if offset > self.length {
             return Err(Error::from(ErrorKind::OutOfMemory));
         }
@@ -153,6 +254,10 @@ impl FileInfo<Created> {
             offset: self.offset,
             metadata: self.metadata,
             file_name: self.file_name,
+            owner: self.owner,
+            tenant: self.tenant,
+            expires_at: self.expires_at,
+            extensions: self.extensions,
         })
     }
 }
@@ -164,10 +269,6 @@ impl FileInfo<Completed> {
 }
 
 impl FileInfo<Terminated> {
-    pub fn offset(&self) -> &u64 {
-        &self.offset
-    }
-
     pub fn file_name(&self) -> &String {
         &self.file_name
     }
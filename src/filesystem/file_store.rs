@@ -1,17 +1,27 @@
 use async_trait::async_trait;
 use bytes::Bytes;
+use futures::stream::BoxStream;
 use std::{
-    error::Error,
     fs::{self, File},
-    io::{BufReader, ErrorKind, Seek, SeekFrom, Write},
-    path::Path,
+    io::{ErrorKind, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
 };
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
 use super::{
-    file_info::{Built, Completed, Created, FileInfo, Terminated, self},
+    extensions::FileInfoExtensions,
+    file_info::{unix_timestamp, Built, Completed, Created, FileInfo},
+    info_store::{InfoStore, JsonInfoStore},
     metadata::Metadata,
 };
 
+/// Size of the chunks read from disk when streaming an upload back.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The bytes of an upload, as streamed back by [`FileStore::read_file`].
+pub type FileStream = BoxStream<'static, std::io::Result<Bytes>>;
+
 pub enum PatchOption {
     Patched(u64),
     Completed(FileInfo<Completed>),
@@ -25,47 +35,101 @@ pub trait FileStore: Send + Sync + Clone {
     async fn delete_file(&self, file_id: &str) -> Result<(), FileStoreError>;
     async fn get_file_info(&self, file_id: &str) -> Result<FileInfo<Created>, FileStoreError>; // return file length and file type
     async fn exists(&self, file_id: &str) -> bool;
+    async fn list_files(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError>;
+    async fn set_expiry(&self, file_id: &str, expires_at: Option<u64>) -> Result<FileInfo<Created>, FileStoreError>;
+    /// Replaces the application data attached to an upload, see [`FileInfoExtensions`].
+    async fn set_extensions(
+        &self,
+        file_id: &str,
+        extensions: FileInfoExtensions,
+    ) -> Result<FileInfo<Created>, FileStoreError>;
+    async fn read_file(&self, file_id: &str, range: Range<u64>) -> Result<FileStream, FileStoreError>;
+
+    /// The least data written by one [`FileStore::patch_file`] call while a PATCH body is still
+    /// arriving, for stores which can't append small writes cheaply.
+    fn min_patch_size(&self) -> usize {
+        0
+    }
+
+    /// Deletes every upload whose expiry has passed, returning them as they were before removal.
+    async fn remove_expired_files(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError> {
+        let now = unix_timestamp();
+        let mut removed = Vec::new();
+
+        let files = self.list_files().await?;
+
+        for file_info in files {
+            if file_info.expires_at().is_some_and(|expires_at| expires_at <= now) {
+                self.delete_file(file_info.id()).await?;
+                crate::metrics::upload_expired(&file_info);
+                removed.push(file_info);
+            }
+        }
+
+        Ok(removed)
+    }
 }
 
 #[derive(Debug)]
 pub enum FileStoreError {
-    CreationError(Box<dyn std::error::Error>),
-    ReadError(Box<dyn std::error::Error>),
-    TerminationError(Box<dyn std::error::Error>),
+    CreationError(Box<dyn std::error::Error + Send + Sync>),
+    ReadError(Box<dyn std::error::Error + Send + Sync>),
+    TerminationError(Box<dyn std::error::Error + Send + Sync>),
+    // the upload would exceed the quota of its owner or tenant.
+    QuotaExceeded,
     Error
 }
 
+impl std::fmt::Display for FileStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CreationError(e) => write!(f, "could not create the upload: {}", e),
+            Self::ReadError(e) => write!(f, "could not access the upload: {}", e),
+            Self::TerminationError(e) => write!(f, "could not terminate the upload: {}", e),
+            Self::QuotaExceeded => f.write_str("the upload exceeds its quota"),
+            Self::Error => f.write_str("the file store failed"),
+        }
+    }
+}
+
+impl std::error::Error for FileStoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CreationError(e) | Self::ReadError(e) | Self::TerminationError(e) => Some(e.as_ref()),
+            Self::QuotaExceeded | Self::Error => None,
+        }
+    }
+}
+
 // NOTE: You can include an Arc<State> for additional logic at the time of the construction of the filestore.
 #[derive(Clone)]
-pub struct LocalFileStore {
+pub struct LocalFileStore<I: InfoStore = JsonInfoStore> {
     root_path: String,
+    info_store: I,
     // state: Arc<State>
 }
 
 impl LocalFileStore {
+    /// Creates a store writing uploads to `root_path`, with their info saved as JSON next to them.
     pub fn new(root_path: String) -> Self {
         Self {
-            root_path
+            info_store: JsonInfoStore::new(root_path.clone()),
+            root_path,
         }
     }
+}
 
-    fn read_file<State>(
-        &self,
-        file_id: &str,
-    ) -> Result<FileInfo<State>, FileStoreError> {
-        let file_dir = std::path::Path::new(self.root_path.as_str()).join(file_id);
-
-        let info_path = file_dir.join("info").with_extension("json");
-
-        let file = match File::open(info_path) {
-            Ok(file) => file,
-            Err(e) => return Err(FileStoreError::ReadError(Box::new(e))),
-        };
-
-        let reader = BufReader::new(file);
+impl<I: InfoStore> LocalFileStore<I> {
+    /// Keeps the upload info in another [`InfoStore`] (e.g. SQLite) instead of JSON files.
+    pub fn with_info_store<J: InfoStore>(self, info_store: J) -> LocalFileStore<J> {
+        LocalFileStore {
+            root_path: self.root_path,
+            info_store,
+        }
+    }
 
-        serde_json::from_reader(reader)   
-            .map_err(|e| FileStoreError::ReadError(Box::new(e)))
+    fn file_path(&self, file_id: &str) -> PathBuf {
+        Path::new(&self.root_path).join(file_id)
     }
 }
 
@@ -76,7 +140,7 @@ impl LocalFileStore {
 /// You should spin up your own.
 /// 
 #[async_trait]
-impl FileStore for LocalFileStore {
+impl<I: InfoStore> FileStore for LocalFileStore<I> {
     async fn build_file(
         &self,
         length: u64,
@@ -103,15 +167,7 @@ impl FileStore for LocalFileStore {
         &self,
         file_id: &str,
     ) -> bool {
-        let file_path = format!("{}/{}", self.root_path, file_id);
-        println!("When checking file existence, file_path: {}", file_path);
-
-        let file = std::fs::File::open(file_path);
-        if file.is_ok() {
-            true
-        } else {
-            false
-        }
+        self.file_path(file_id).is_file()
     }
 
     async fn create_file(
@@ -123,10 +179,7 @@ impl FileStore for LocalFileStore {
             fs::create_dir_all(file_dir).map_err(|e| FileStoreError::CreationError(Box::new(e)))?;
         }
 
-        // NOTE do we have the filename from the metadata here?
-        let file_name = file_dir.join(file_info.name());
-        // NOTE this creates a new file; for our bucket filestore we will just create a new file in the bucket.
-        // and then name the info.json (it should just be inside)
+        let file_name = self.file_path(file_info.id());
         if let Err(e) = match File::options()
             .read(true)
             .write(true)
@@ -146,23 +199,11 @@ impl FileStore for LocalFileStore {
         };
 
         let file_info = file_info.mark_as_created(file_name);
-        
-        if let Err(e) = match File::options()
-            .read(true)
-            .write(true)
-            .create_new(true)
-            .open(file_dir.join("info").with_extension("json"))
-        {
-            Ok(info) => {
-                serde_json::to_writer(info, &file_info).map_err(|e| e.into())
-            }
-            Err(e) => Err(e.into()),
-        } {
-            return Err(FileStoreError::CreationError(e));
-        };
 
-        Ok(file_info)
+        self.info_store.create_info(&file_info).await?;
+        tracing::debug!(upload_id = file_info.id(), length = file_info.length(), path = file_name, "created upload");
 
+        Ok(file_info)
     }
 
     async fn patch_file(
@@ -171,18 +212,18 @@ impl FileStore for LocalFileStore {
         offset: u64,
         data: &mut [u8],
     ) -> Result<PatchOption, FileStoreError> {
-        let mut file: FileInfo<Created>  = self.read_file(file_id)?;
+        let mut file_info = self.info_store.get_info(file_id).await?;
 
-        if *file.offset() != offset {
+        if *file_info.offset() != offset {
             return Err(FileStoreError::ReadError(Box::new(
                 std::io::Error::from(ErrorKind::InvalidInput),
             )));
         }
 
-        let file_path = format!("{}/{}", self.root_path, file_id);
-        println!("When patching file, file_path: {}", file_path);
+        file_info.set_offset(offset + data.len() as u64)
+            .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
 
-        let file_dir = Path::new(&self.root_path).join(file_id);
+        let file_path = self.file_path(file_id);
 
         let mut file = std::fs::OpenOptions::new()
             .read(true)
@@ -196,26 +237,25 @@ impl FileStore for LocalFileStore {
         file.write_all(data)
             .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
 
-        let file_info_path = file_dir.join("info").with_extension("json");
-
-        let mut file_info = File::options().write(true).open(file_info_path).unwrap();
-
-        serde_json::to_writer(&mut file_info, &file).unwrap();
+        self.info_store.update_info(&file_info).await?;
 
-        // also, remember to check for the last part so if it's the last one, we can start the merge process
-        // How to check for the file's metdata here?
+        let new_offset = *file_info.offset();
+        tracing::debug!(upload_id = file_id, offset, bytes = data.len(), new_offset, "patched upload");
 
-        Ok(PatchOption::Patched(metadata.len()))
+        match file_info.check_completion() {
+            Some(completed) => Ok(PatchOption::Completed(completed)),
+            None => Ok(PatchOption::Patched(new_offset)),
+        }
     }
 
     async fn delete_file(
         &self,
         file_id: &str,
     ) -> Result<(), FileStoreError> {
-        let file_path = format!("{}/{}", self.root_path, file_id);
-        println!("When deleting file, file_path: {}", file_path);
+        std::fs::remove_file(self.file_path(file_id)).map_err(|e| FileStoreError::TerminationError(Box::new(e)))?;
 
-        let file = std::fs::remove_file(file_path).map_err(|e| FileStoreError::TerminationError(Box::new(e)))?;
+        self.info_store.delete_info(file_id).await?;
+        tracing::debug!(upload_id = file_id, "deleted upload");
 
         Ok(())
     }        
@@ -224,7 +264,67 @@ impl FileStore for LocalFileStore {
         &self,
         file_id: &str
     ) -> Result<FileInfo<Created>, FileStoreError> {
-        self.read_file(file_id)
+        self.info_store.get_info(file_id).await
+    }
+
+    async fn list_files(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError> {
+        self.info_store.list_infos().await
+    }
+
+    async fn set_expiry(
+        &self,
+        file_id: &str,
+        expires_at: Option<u64>,
+    ) -> Result<FileInfo<Created>, FileStoreError> {
+        let mut file_info = self.info_store.get_info(file_id).await?;
+        file_info.set_expiry(expires_at);
+
+        self.info_store.update_info(&file_info).await?;
+
+        Ok(file_info)
+    }
+
+    async fn set_extensions(
+        &self,
+        file_id: &str,
+        extensions: FileInfoExtensions,
+    ) -> Result<FileInfo<Created>, FileStoreError> {
+        let mut file_info = self.info_store.get_info(file_id).await?;
+        file_info.set_extensions(extensions);
+
+        self.info_store.update_info(&file_info).await?;
+
+        Ok(file_info)
+    }
+
+    async fn read_file(
+        &self,
+        file_id: &str,
+        range: Range<u64>,
+    ) -> Result<FileStream, FileStoreError> {
+        let mut file = tokio::fs::File::open(self.file_path(file_id))
+            .await
+            .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+
+        file.seek(SeekFrom::Start(range.start))
+            .await
+            .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+
+        let reader = file.take(range.end - range.start);
+
+        let stream = futures::stream::try_unfold(reader, |mut reader| async move {
+            let mut buffer = vec![0; READ_CHUNK_SIZE];
+            let read = reader.read(&mut buffer).await?;
+
+            if read == 0 {
+                return Ok(None);
+            }
+            buffer.truncate(read);
+
+            Ok(Some((Bytes::from(buffer), reader)))
+        });
+
+        Ok(Box::pin(stream))
     }
 }
 
@@ -240,8 +340,6 @@ mod tests {
         Built,
         Created,
         Patched,
-        Completed,
-        Terminated,
     }
 
     // creating separate test dirs because the tests are run in parallel...
@@ -251,24 +349,22 @@ mod tests {
                 Self::Built => "_built",
                 Self::Created => "_created",
                 Self::Patched => "_patched",
-                Self::Completed => "_completed",
-                Self::Terminated => "_terminated",
             }
         }
     }
 
-    fn cleanup_test_directory(test_state: FileStoreTestState) {
-        // todo must be configurable or detected.
-        let root_path = format!("/Users/adambrowne/projects/axum-tus/src/root_test_path{}", test_state.name());
-        let info_path = format!("{}/{}", root_path, "test_local_file.info.json");
+    fn test_root_path(test_state: FileStoreTestState) -> String {
+        let root_path = std::env::temp_dir().join(format!("axum_tus_root_test_path{}", test_state.name()));
 
-        let _ = std::fs::remove_file(info_path);
-        let _ = std::fs::remove_dir_all(root_path);
+        root_path.to_str().unwrap().to_string()
+    }
+
+    fn cleanup_test_directory(test_state: FileStoreTestState) {
+        let _ = std::fs::remove_dir_all(test_root_path(test_state));
     }
 
     async fn build_and_create_test_file(test_state: FileStoreTestState) -> Result<FileInfo<Created>, FileStoreError> {
-        let root_path = format!("/Users/adambrowne/projects/axum-tus/src/root_test_path{}", test_state.name());
-        let local_file_store = LocalFileStore::new(root_path);
+        let local_file_store = LocalFileStore::new(test_root_path(test_state));
 
         // 16 megabytes upload length
         let upload_length: u64 = 16361047;
@@ -307,10 +403,10 @@ mod tests {
         }
     }
 
-    async fn patch_byte_offset_of_file(file_store: &LocalFileStore, file_info: &FileInfo<Created>, offset: u64, data: &mut [u8]) -> Result<u64, FileStoreError> {
+    async fn patch_byte_offset_of_file<I: InfoStore>(file_store: &LocalFileStore<I>, file_info: &FileInfo<Created>, offset: u64, data: &mut [u8]) -> Result<u64, FileStoreError> {
         let file_id = file_info.id();
 
-        let final_offset: Result<u64, FileStoreError> = match file_store.patch_file(&file_id, offset, data).await {
+        let final_offset: Result<u64, FileStoreError> = match file_store.patch_file(file_id, offset, data).await {
             Ok(result) => {
                 let final_offset = match result {
                     PatchOption::Patched(offset) => offset,
@@ -346,13 +442,12 @@ mod tests {
         
         cleanup_test_directory(test_state);
 
-        let file_info = build_and_create_test_file(test_state.clone()).await.unwrap();
+        let file_info = build_and_create_test_file(test_state).await.unwrap();
 
-        let root_path = format!("/Users/adambrowne/projects/axum-tus/src/root_test_path{}", test_state.clone().name());
-        let local_file_store = LocalFileStore::new(root_path);
+        let local_file_store = LocalFileStore::new(test_root_path(test_state));
 
         // For this test we're just splitting the file in two.
-        let all_file_data = std::fs::read("/Users/adambrowne/projects/axum-tus/src/filesystem/test_local_file.mov").unwrap();
+        let all_file_data: Vec<u8> = (0..16361047u32).map(|i| i as u8).collect();
         let midpoint = all_file_data.len() / 2;
 
         let mut first_half_bytes = all_file_data[..midpoint].to_vec();
@@ -365,4 +460,29 @@ mod tests {
         // proper upload length. should be completed here.
         assert_eq!(second_offset, 16361047);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_local_file_with_memory_info_store() {
+        let test_state = FileStoreTestState::Created;
+
+        cleanup_test_directory(test_state);
+
+        let root_path = test_root_path(test_state);
+        let local_file_store = LocalFileStore::new(root_path.clone())
+            .with_info_store(crate::filesystem::info_store::MemoryInfoStore::new());
+
+        let file_info = local_file_store.build_file(4, None).await.unwrap();
+        let file_info = local_file_store.create_file(file_info).await.unwrap();
+        let file_id = file_info.id().to_string();
+
+        assert!(local_file_store.exists(&file_id).await);
+        assert!(!Path::new(&root_path).join(format!("{}.info.json", file_id)).exists());
+
+        let offset = patch_byte_offset_of_file(&local_file_store, &file_info, 0, &mut b"abcd".to_vec()).await.unwrap();
+        assert_eq!(offset, 4);
+        assert_eq!(std::fs::read(Path::new(&root_path).join(&file_id)).unwrap(), b"abcd");
+
+        local_file_store.delete_file(&file_id).await.unwrap();
+        assert!(local_file_store.get_file_info(&file_id).await.is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, ErrorKind},
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use super::{
+    file_info::{Created, FileInfo},
+    file_store::FileStoreError,
+};
+
+/// Persists the state of an upload (offset, length, metadata, expiry, owner), independently of
+/// where its bytes are written.
+///
+/// Every data store ([`LocalFileStore`](super::file_store::LocalFileStore),
+/// [`ObjectFileStore`](super::object_file_store::ObjectFileStore)) is generic over its `InfoStore`,
+/// so any data backend can be combined with any info backend.
+#[async_trait]
+pub trait InfoStore: Send + Sync + Clone {
+    async fn create_info(&self, file_info: &FileInfo<Created>) -> Result<(), FileStoreError>;
+    async fn get_info(&self, file_id: &str) -> Result<FileInfo<Created>, FileStoreError>;
+    async fn update_info(&self, file_info: &FileInfo<Created>) -> Result<(), FileStoreError>;
+    async fn delete_info(&self, file_id: &str) -> Result<(), FileStoreError>;
+    async fn list_infos(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError>;
+}
+
+/// Saves every [`FileInfo`] as `<root_path>/<id>.info.json`.
+#[derive(Clone)]
+pub struct JsonInfoStore {
+    root_path: String,
+}
+
+impl JsonInfoStore {
+    pub fn new(root_path: String) -> Self {
+        Self {
+            root_path
+        }
+    }
+
+    fn info_path(&self, file_id: &str) -> PathBuf {
+        PathBuf::from(&self.root_path).join(format!("{}.info.json", file_id))
+    }
+
+    fn write_info(&self, file_info: &FileInfo<Created>, create_new: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        fs::create_dir_all(&self.root_path)?;
+
+        let file = File::options()
+            .write(true)
+            .create_new(create_new)
+            .truncate(!create_new)
+            .open(self.info_path(file_info.id()))?;
+
+        serde_json::to_writer(file, file_info)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl InfoStore for JsonInfoStore {
+    async fn create_info(&self, file_info: &FileInfo<Created>) -> Result<(), FileStoreError> {
+        self.write_info(file_info, true)
+            .map_err(FileStoreError::CreationError)
+    }
+
+    async fn get_info(&self, file_id: &str) -> Result<FileInfo<Created>, FileStoreError> {
+        let file = File::open(self.info_path(file_id))
+            .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| FileStoreError::ReadError(Box::new(e)))
+    }
+
+    async fn update_info(&self, file_info: &FileInfo<Created>) -> Result<(), FileStoreError> {
+        self.write_info(file_info, false)
+            .map_err(FileStoreError::ReadError)
+    }
+
+    async fn delete_info(&self, file_id: &str) -> Result<(), FileStoreError> {
+        fs::remove_file(self.info_path(file_id))
+            .map_err(|e| FileStoreError::TerminationError(Box::new(e)))
+    }
+
+    async fn list_infos(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError> {
+        let entries = match fs::read_dir(&self.root_path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(FileStoreError::ReadError(Box::new(e))),
+        };
+
+        let mut infos = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+            let file_name = entry.file_name();
+
+            if let Some(file_id) = file_name.to_str().and_then(|name| name.strip_suffix(".info.json")) {
+                infos.push(self.get_info(file_id).await?);
+            }
+        }
+
+        Ok(infos)
+    }
+}
+
+/// Keeps every [`FileInfo`] in memory; uploads are lost when the process exits.
+#[derive(Clone, Default)]
+pub struct MemoryInfoStore {
+    infos: Arc<RwLock<HashMap<String, FileInfo<Created>>>>,
+}
+
+impl MemoryInfoStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+#[async_trait]
+impl InfoStore for MemoryInfoStore {
+    async fn create_info(&self, file_info: &FileInfo<Created>) -> Result<(), FileStoreError> {
+        let mut infos = self.infos.write().unwrap();
+
+        if infos.contains_key(file_info.id()) {
+            return Err(FileStoreError::CreationError(Box::new(
+                std::io::Error::from(ErrorKind::AlreadyExists),
+            )));
+        }
+
+        infos.insert(file_info.id().to_string(), file_info.clone());
+
+        Ok(())
+    }
+
+    async fn get_info(&self, file_id: &str) -> Result<FileInfo<Created>, FileStoreError> {
+        match self.infos.read().unwrap().get(file_id) {
+            Some(file_info) => Ok(file_info.clone()),
+            None => Err(FileStoreError::ReadError(Box::new(
+                std::io::Error::from(ErrorKind::NotFound),
+            ))),
+        }
+    }
+
+    async fn update_info(&self, file_info: &FileInfo<Created>) -> Result<(), FileStoreError> {
+        match self.infos.write().unwrap().get_mut(file_info.id()) {
+            Some(existing) => {
+                *existing = file_info.clone();
+                Ok(())
+            },
+            None => Err(FileStoreError::ReadError(Box::new(
+                std::io::Error::from(ErrorKind::NotFound),
+            ))),
+        }
+    }
+
+    async fn delete_info(&self, file_id: &str) -> Result<(), FileStoreError> {
+        match self.infos.write().unwrap().remove(file_id) {
+            Some(_) => Ok(()),
+            None => Err(FileStoreError::TerminationError(Box::new(
+                std::io::Error::from(ErrorKind::NotFound),
+            ))),
+        }
+    }
+
+    async fn list_infos(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError> {
+        Ok(self.infos.read().unwrap().values().cloned().collect())
+    }
+}
@@ -1,17 +1,39 @@
-use base64::Engine as _;
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, error::Error, fmt::Display};
+use base64::{
+    alphabet,
+    engine::{general_purpose::STANDARD, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine as _,
+};
+use serde::{
+    de::{
+        value::{Error as ValueError, MapDeserializer, SeqDeserializer},
+        Error as _, IntoDeserializer, MapAccess, Visitor,
+    },
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{error::Error, fmt::Display, str::FromStr};
+
+// clients disagree on padding, so accept values with or without it.
+const DECODER: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
 
 /// A struct representing the metadata associated with an uploaded file.
-#[derive(Default, Debug, Serialize, Deserialize)]
-pub struct Metadata(HashMap<String, String>);
+///
+/// Pairs keep the order they were sent in, and values are kept as the decoded bytes, which
+/// don't have to be UTF-8. It is serialized as a map of keys to base64 encoded values.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Metadata(Vec<(String, Vec<u8>)>);
 
 /// An error type representing errors that can occur while dealing with metadata.
 #[derive(Debug, PartialEq)]
 pub enum MetadataError {
     InvalidKey,
+    DuplicateKey(String),
     DecodeError(String),
     InvalidMetadataFormat,
+    DeserializeError(String),
 }
 
 impl Error for MetadataError {}
@@ -22,20 +44,44 @@ impl Display for MetadataError {
     }
 }
 
+/// Keys must not be empty, and only contain visible ASCII characters other than `,`.
+fn validate_key(key: &str) -> Result<(), MetadataError> {
+    if key.is_empty() || !key.bytes().all(|b| b.is_ascii_graphic() && b != b',') {
+        return Err(MetadataError::InvalidKey);
+    }
+
+    Ok(())
+}
+
 impl Metadata {
     pub fn new() -> Self {
         Default::default()
     }
 
-    pub fn get_raw(&self, key: &str) -> Result<Vec<u8>, MetadataError> {
-        let value = match self.0.get(key) {
-            Some(v) => v,
-            None => return Err(MetadataError::InvalidKey),
-        };
+    /// Appends a pair, rejecting invalid and duplicate keys.
+    pub fn insert(&mut self, key: &str, value: impl Into<Vec<u8>>) -> Result<(), MetadataError> {
+        validate_key(key)?;
 
-        match base64::engine::general_purpose::STANDARD.decode(value) {
-            Ok(decoded) => Ok(decoded),
-            Err(e) => Err(MetadataError::DecodeError(e.to_string())),
+        if self.get(key).is_some() {
+            return Err(MetadataError::DuplicateKey(key.to_string()));
+        }
+
+        self.0.push((key.to_string(), value.into()));
+
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    pub fn get_raw(&self, key: &str) -> Result<Vec<u8>, MetadataError> {
+        match self.get(key) {
+            Some(value) => Ok(value.to_vec()),
+            None => Err(MetadataError::InvalidKey),
         }
     }
 
@@ -44,18 +90,94 @@ impl Metadata {
     }
 
     pub fn try_get_key(&self, key: &str) -> Option<String> {
-        match self.get_raw(key) {
-            Ok(v) => {
-                let value = String::from_utf8_lossy(&v);
-                Some(value.to_string())
-            },
-            Err(_) => None,
-        }
+        self.get(key).map(|value| String::from_utf8_lossy(value).to_string())
+    }
+
+    /// The value of `key`, if it is valid UTF-8.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// Parses the value of `key`, e.g. as a number, returning `None` if the key isn't set.
+    pub fn get_parsed<T>(&self, key: &str) -> Result<Option<T>, MetadataError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+
+        std::str::from_utf8(value)
+            .map_err(|e| e.to_string())
+            .and_then(|value| value.parse::<T>().map_err(|e| e.to_string()))
+            .map(Some)
+            .map_err(|e| MetadataError::DeserializeError(format!("`{}`: {}", key, e)))
+    }
+
+    /// Deserializes the pairs into `T`, typically a `#[derive(Deserialize)]` struct whose fields
+    /// are metadata keys. Values are parsed into the type of their field (strings, numbers,
+    /// booleans, bytes, unit enums, or comma separated sequences), and a set key is `Some` for
+    /// `Option` fields.
+    ///
+    /// ```
+    /// use axum_tus::Metadata;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct UploadMeta {
+    ///     filename: String,
+    ///     project_id: u64,
+    ///     is_confidential: Option<bool>,
+    /// }
+    ///
+    /// let metadata = Metadata::try_from("filename YS5wZGY=,project_id NDI=,is_confidential dHJ1ZQ==").unwrap();
+    /// let meta: UploadMeta = metadata.deserialize_into().unwrap();
+    ///
+    /// assert_eq!(meta.filename, "a.pdf");
+    /// assert_eq!(meta.project_id, 42);
+    /// assert_eq!(meta.is_confidential, Some(true));
+    /// ```
+    pub fn deserialize_into<'de, T: Deserialize<'de>>(&'de self) -> Result<T, MetadataError> {
+        let map = MapDeserializer::new(self.0.iter().map(|(key, value)| {
+            (key.as_str(), ValueDeserializer { key, value })
+        }));
+
+        T::deserialize(map).map_err(|e: ValueError| MetadataError::DeserializeError(e.to_string()))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.0.iter().map(|(key, _)| key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.0.iter().map(|(key, value)| (key.as_str(), value.as_slice()))
     }
 
     pub fn len(&self) -> usize {
         self.0.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Encodes the pairs as an `Upload-Metadata` header, leaving out the value of empty values.
+    pub fn encode(&self) -> String {
+        self.iter()
+            .map(|(key, value)| match value.is_empty() {
+                true => key.to_string(),
+                false => format!("{} {}", key, STANDARD.encode(value)),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl Display for Metadata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.encode())
+    }
 }
 
 impl TryFrom<&str> for Metadata {
@@ -63,42 +185,384 @@ impl TryFrom<&str> for Metadata {
 
     /// Attempts to parse a metadata string into a [`Metadata`] instance.
     ///
-    /// The given string should follow the tus [`Upload-Metadata`](https://tus.io/protocols/resumable-upload.html#upload-metadata) definition .
+    /// The given string should follow the tus [`Upload-Metadata`](https://tus.io/protocols/resumable-upload.html#upload-metadata) definition:
+    /// comma separated pairs of a key and its base64 encoded value, separated by a space. The
+    /// value (and the space) may be left out, and an empty header has no pairs.
+    ///
+    /// ```
+    /// use axum_tus::Metadata;
+    ///
+    /// let metadata = Metadata::try_from("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential").unwrap();
+    ///
+    /// assert_eq!(metadata.try_file_name().unwrap(), "world_domination_plan.pdf");
+    /// assert_eq!(metadata.get("is_confidential"), Some(&b""[..]));
     /// ```
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        if value.is_empty() {
-            return Err(MetadataError::InvalidMetadataFormat);
-        }
-
         let mut metadata = Metadata::new();
 
+        if value.trim().is_empty() {
+            return Ok(metadata);
+        }
+
         for pair in value.split(',') {
             let pair = pair.trim();
 
+            // empty list elements are allowed by HTTP, e.g. a trailing comma.
             if pair.is_empty() {
                 continue;
             }
 
-            let parts: Vec<&str> = pair.split(' ').map(|v| v.trim()).collect();
+            let (key, value) = match pair.split_once(' ') {
+                Some((key, value)) => (key, value.trim()),
+                None => (pair, ""),
+            };
 
-            if parts.is_empty() || parts.len() > 2 {
+            validate_key(key)?;
+
+            if value.contains(|c: char| c.is_whitespace()) {
                 return Err(MetadataError::InvalidMetadataFormat);
             }
 
-            if parts[0].is_empty() {
-                return Err(MetadataError::InvalidKey);
+            let value = DECODER
+                .decode(value)
+                .map_err(|e| MetadataError::DecodeError(e.to_string()))?;
+
+            metadata.insert(key, value)?;
+        }
+
+        Ok(metadata)
+    }
+}
+
+impl FromStr for Metadata {
+    type Err = MetadataError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Metadata::try_from(value)
+    }
+}
+
+impl Serialize for Metadata {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+
+        for (key, value) in self.iter() {
+            map.serialize_entry(key, &STANDARD.encode(value))?;
+        }
+
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Metadata {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MetadataVisitor;
+
+        impl<'de> Visitor<'de> for MetadataVisitor {
+            type Value = Metadata;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a map of keys to base64 encoded values")
             }
 
-            if let (Some(key), value) = (parts.get(0), parts.get(1)) {
-                let value = match value {
-                    Some(v) => v.to_string(),
-                    None => String::default(),
-                };
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Metadata, A::Error> {
+                let mut metadata = Metadata::new();
 
-                metadata.0.insert(key.to_string(), value);
+                while let Some((key, value)) = map.next_entry::<String, String>()? {
+                    let value = DECODER.decode(&value).map_err(serde::de::Error::custom)?;
+                    metadata.insert(&key, value).map_err(serde::de::Error::custom)?;
+                }
+
+                Ok(metadata)
             }
         }
 
-        Ok(metadata)
+        deserializer.deserialize_map(MetadataVisitor)
+    }
+}
+
+/// Deserializes a single metadata value into the type its field asks for.
+struct ValueDeserializer<'de> {
+    key: &'de str,
+    value: &'de [u8],
+}
+
+impl<'de> ValueDeserializer<'de> {
+    fn error(&self, reason: impl Display) -> ValueError {
+        ValueError::custom(format!("invalid value for `{}`: {}", self.key, reason))
+    }
+
+    fn str(&self) -> Result<&'de str, ValueError> {
+        std::str::from_utf8(self.value).map_err(|_| self.error("not valid UTF-8"))
+    }
+
+    fn parse<T>(&self) -> Result<T, ValueError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.str()?.trim().parse().map_err(|e| self.error(e))
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let value = self.parse()?;
+                visitor.$visit::<ValueError>(value).map_err(|e| self.error(e))
+            }
+        )*
+    };
+}
+
+impl<'de> serde::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match std::str::from_utf8(self.value) {
+            Ok(value) => visitor.visit_borrowed_str::<ValueError>(value),
+            Err(_) => visitor.visit_borrowed_bytes::<ValueError>(self.value),
+        }
+        .map_err(|e| self.error(e))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let value = match self.str()?.trim() {
+            "true" | "1" => true,
+            "false" | "0" => false,
+            value => return Err(self.error(format!("expected a boolean, found `{}`", value))),
+        };
+
+        visitor.visit_bool(value)
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let value = self.str()?;
+        visitor.visit_borrowed_str::<ValueError>(value).map_err(|e| self.error(e))
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_bytes::<ValueError>(self.value).map_err(|e| self.error(e))
     }
-}
\ No newline at end of file
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let key = self.key;
+        let items = self
+            .str()?
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(move |item| ValueDeserializer { key, value: item.as_bytes() });
+
+        let mut seq = SeqDeserializer::new(items);
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+
+        Ok(value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let value = self.str()?;
+        let variant = serde::de::value::BorrowedStrDeserializer::<ValueError>::new(value);
+        visitor.visit_enum(variant).map_err(|e| self.error(e))
+    }
+
+    serde::forward_to_deserialize_any! {
+        unit unit_struct tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ValueError> for ValueDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata() {
+        let metadata = Metadata::try_from(" b Yg==, a YQ ,empty ,binary //8=,").unwrap();
+        let pairs: Vec<(&str, &[u8])> = metadata.iter().collect();
+        assert_eq!(pairs, vec![("b", &b"b"[..]), ("a", b"a"), ("empty", b""), ("binary", &[0xff, 0xff])]);
+
+        assert_eq!(Metadata::try_from("").unwrap(), Metadata::new());
+        assert_eq!(Metadata::try_from("a YQ==,a Yg=="), Err(MetadataError::DuplicateKey("a".to_string())));
+        assert_eq!(Metadata::try_from("ké YQ=="), Err(MetadataError::InvalidKey));
+        assert_eq!(Metadata::try_from("a YQ== Yg=="), Err(MetadataError::InvalidMetadataFormat));
+        assert!(matches!(Metadata::try_from("a not-base64"), Err(MetadataError::DecodeError(_))));
+    }
+
+    #[test]
+    fn test_metadata_json_keeps_order() {
+        let metadata = Metadata::try_from("z eg==,a YQ==,m //8=").unwrap();
+        let json = serde_json::to_string(&metadata).unwrap();
+
+        assert_eq!(json, r#"{"z":"eg==","a":"YQ==","m":"//8="}"#);
+        assert_eq!(serde_json::from_str::<Metadata>(&json).unwrap(), metadata);
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Visibility {
+        Public,
+        Private,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct UploadMeta {
+        filename: String,
+        filetype: Option<String>,
+        project_id: u64,
+        is_confidential: bool,
+        visibility: Visibility,
+        tags: Vec<String>,
+        #[serde(with = "serde_bytes_vec")]
+        thumbnail: Vec<u8>,
+    }
+
+    mod serde_bytes_vec {
+        pub fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+            struct BytesVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+                type Value = Vec<u8>;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("bytes")
+                }
+
+                fn visit_bytes<E>(self, value: &[u8]) -> Result<Vec<u8>, E> {
+                    Ok(value.to_vec())
+                }
+            }
+
+            deserializer.deserialize_bytes(BytesVisitor)
+        }
+    }
+
+    fn metadata(pairs: &[(&str, &[u8])]) -> Metadata {
+        let mut metadata = Metadata::new();
+        for (key, value) in pairs {
+            metadata.insert(key, value.to_vec()).unwrap();
+        }
+        metadata
+    }
+
+    #[test]
+    fn test_deserialize_into_struct() {
+        let mut values = metadata(&[
+            ("filename", b"a.png"),
+            ("project_id", b"42"),
+            ("is_confidential", b"true"),
+            ("visibility", b"private"),
+            ("tags", b"red, blue"),
+            ("thumbnail", &[0xff, 0x00]),
+            ("ignored", b"x"),
+        ]);
+
+        let meta: UploadMeta = values.deserialize_into().unwrap();
+        assert_eq!(meta, UploadMeta {
+            filename: "a.png".to_string(),
+            filetype: None,
+            project_id: 42,
+            is_confidential: true,
+            visibility: Visibility::Private,
+            tags: vec!["red".to_string(), "blue".to_string()],
+            thumbnail: vec![0xff, 0x00],
+        });
+        assert_eq!(values.get_parsed::<u64>("project_id"), Ok(Some(42)));
+        assert_eq!(values.get_str("thumbnail"), None);
+
+        values.0.retain(|(key, _)| key != "project_id");
+        assert_eq!(
+            values.deserialize_into::<UploadMeta>(),
+            Err(MetadataError::DeserializeError("missing field `project_id`".to_string()))
+        );
+
+        let mistyped = metadata(&[("project_id", b"forty-two")]);
+        assert_eq!(
+            mistyped.get_parsed::<u64>("project_id"),
+            Err(MetadataError::DeserializeError("`project_id`: invalid digit found in string".to_string()))
+        );
+        assert!(matches!(
+            mistyped.deserialize_into::<UploadMeta>(),
+            Err(MetadataError::DeserializeError(e)) if e.contains("invalid value for `project_id`")
+        ));
+    }
+
+    fn arb_metadata() -> impl Strategy<Value = Metadata> {
+        let pair = ("[!-+--~]{1,12}", proptest::collection::vec(any::<u8>(), 0..32));
+
+        proptest::collection::vec(pair, 0..8).prop_map(|pairs| {
+            let mut metadata = Metadata::new();
+            for (key, value) in pairs {
+                // generated keys may repeat, keep the first one.
+                let _ = metadata.insert(&key, value);
+            }
+            metadata
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_encode_parse_round_trip(metadata in arb_metadata()) {
+            let parsed = Metadata::try_from(metadata.encode().as_str()).unwrap();
+            prop_assert_eq!(parsed, metadata);
+        }
+
+        #[test]
+        fn test_parse_never_panics(header in "\\PC*") {
+            let _ = Metadata::try_from(header.as_str());
+        }
+
+        #[test]
+        fn test_parse_encode_is_canonical(metadata in arb_metadata()) {
+            let encoded = metadata.encode();
+            let reencoded = Metadata::try_from(encoded.as_str()).unwrap().encode();
+            prop_assert_eq!(reencoded, encoded);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt::Display;
+
+use super::metadata::{Metadata, MetadataError};
+
+/// Business rules on the `Upload-Metadata` of new uploads, checked on creation, see
+/// [`TusConfig::with_metadata_policy`](crate::TusConfig::with_metadata_policy).
+///
+/// Creations breaking the policy are rejected with `400 Bad Request` and the
+/// [`MetadataViolation`] as the body.
+#[derive(Debug, Clone, Default)]
+pub struct MetadataPolicy {
+    required_keys: Vec<String>,
+    allowed_keys: Option<Vec<String>>,
+    max_header_size: Option<usize>,
+    max_value_length: Option<usize>,
+    allowed_file_types: Option<Vec<String>>,
+    allowed_extensions: Option<Vec<String>>,
+}
+
+/// The reason an `Upload-Metadata` header was rejected by a [`MetadataPolicy`].
+#[derive(Debug, PartialEq)]
+pub enum MetadataViolation {
+    Invalid(MetadataError),
+    MissingKey(String),
+    KeyNotAllowed(String),
+    HeaderTooLarge { size: usize, max: usize },
+    ValueTooLong { key: String, length: usize, max: usize },
+    FileTypeNotAllowed(String),
+    ExtensionNotAllowed(String),
+}
+
+impl std::error::Error for MetadataViolation {}
+
+impl Display for MetadataViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) => write!(f, "invalid Upload-Metadata: {}", e),
+            Self::MissingKey(key) => write!(f, "missing required metadata key `{}`", key),
+            Self::KeyNotAllowed(key) => write!(f, "metadata key `{}` is not allowed", key),
+            Self::HeaderTooLarge { size, max } => {
+                write!(f, "Upload-Metadata is {} bytes, the maximum is {}", size, max)
+            },
+            Self::ValueTooLong { key, length, max } => {
+                write!(f, "metadata value of `{}` is {} bytes, the maximum is {}", key, length, max)
+            },
+            Self::FileTypeNotAllowed(file_type) => write!(f, "file type `{}` is not allowed", file_type),
+            Self::ExtensionNotAllowed(file_name) => write!(f, "file extension of `{}` is not allowed", file_name),
+        }
+    }
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+impl MetadataPolicy {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Keys every upload must set, e.g. `filename` and `filetype`.
+    pub fn with_required_keys(mut self, keys: &[&str]) -> Self {
+        self.required_keys = to_strings(keys);
+        self
+    }
+
+    /// The only keys uploads may set; any key is allowed by default.
+    pub fn with_allowed_keys(mut self, keys: &[&str]) -> Self {
+        self.allowed_keys = Some(to_strings(keys));
+        self
+    }
+
+    /// The maximum size of the raw `Upload-Metadata` header, in bytes.
+    pub fn with_max_header_size(mut self, max: usize) -> Self {
+        self.max_header_size = Some(max);
+        self
+    }
+
+    /// The maximum length of every decoded value, in bytes.
+    pub fn with_max_value_length(mut self, max: usize) -> Self {
+        self.max_value_length = Some(max);
+        self
+    }
+
+    /// MIME types allowed for the `filetype` value, either exact (`application/pdf`) or a whole
+    /// type (`image/*`). Uploads without a `filetype` are only rejected if it is required.
+    pub fn with_allowed_file_types(mut self, file_types: &[&str]) -> Self {
+        self.allowed_file_types = Some(file_types.iter().map(|t| t.to_ascii_lowercase()).collect());
+        self
+    }
+
+    /// Extensions allowed for the `filename` value, e.g. `["pdf", "png"]`. Uploads without a
+    /// `filename` are only rejected if it is required.
+    pub fn with_allowed_extensions(mut self, extensions: &[&str]) -> Self {
+        self.allowed_extensions = Some(
+            extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Checks a raw `Upload-Metadata` header, `None` when the request didn't send one.
+    pub fn validate(&self, header: Option<&str>) -> Result<(), MetadataViolation> {
+        let header = header.unwrap_or_default();
+
+        if let Some(max) = self.max_header_size {
+            if header.len() > max {
+                return Err(MetadataViolation::HeaderTooLarge { size: header.len(), max });
+            }
+        }
+
+        let metadata = match header.trim() {
+            "" => Metadata::new(),
+            header => Metadata::try_from(header).map_err(MetadataViolation::Invalid)?,
+        };
+
+        let keys: Vec<&String> = metadata.keys().collect();
+
+        if let Some(key) = self.required_keys.iter().find(|key| !keys.contains(key)) {
+            return Err(MetadataViolation::MissingKey(key.clone()));
+        }
+
+        for key in keys {
+            if self.allowed_keys.as_ref().is_some_and(|allowed| !allowed.contains(key)) {
+                return Err(MetadataViolation::KeyNotAllowed(key.clone()));
+            }
+
+            let value = metadata.get_raw(key).map_err(MetadataViolation::Invalid)?;
+
+            if let Some(max) = self.max_value_length {
+                if value.len() > max {
+                    return Err(MetadataViolation::ValueTooLong { key: key.clone(), length: value.len(), max });
+                }
+            }
+        }
+
+        if let (Some(allowed), Some(file_type)) = (&self.allowed_file_types, metadata.try_get_key("filetype")) {
+            if !file_type_allowed(allowed, &file_type) {
+                return Err(MetadataViolation::FileTypeNotAllowed(file_type));
+            }
+        }
+
+        if let (Some(allowed), Some(file_name)) = (&self.allowed_extensions, metadata.try_file_name()) {
+            let extension = file_name
+                .rsplit_once('.')
+                .map(|(_, extension)| extension.to_ascii_lowercase());
+
+            if !extension.is_some_and(|extension| allowed.contains(&extension)) {
+                return Err(MetadataViolation::ExtensionNotAllowed(file_name));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn file_type_allowed(allowed: &[String], file_type: &str) -> bool {
+    // ignore parameters such as `; charset=utf-8`
+    let essence = file_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+    allowed.iter().any(|pattern| match pattern.strip_suffix("/*") {
+        Some(kind) => essence.split_once('/').is_some_and(|(essence_kind, _)| essence_kind == kind),
+        None => *pattern == essence,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::*;
+
+    fn header(pairs: &[(&str, &str)]) -> String {
+        pairs
+            .iter()
+            .map(|(key, value)| format!("{} {}", key, STANDARD.encode(value)))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    #[test]
+    fn test_metadata_policy() {
+        let policy = MetadataPolicy::new()
+            .with_required_keys(&["filename", "filetype"])
+            .with_allowed_keys(&["filename", "filetype", "project"])
+            .with_max_value_length(24)
+            .with_allowed_file_types(&["image/*", "application/pdf"])
+            .with_allowed_extensions(&["png", ".pdf"]);
+
+        let valid = header(&[("filename", "scan.PDF"), ("filetype", "application/pdf; q=1")]);
+        assert_eq!(policy.validate(Some(&valid)), Ok(()));
+        assert_eq!(policy.validate(Some(&header(&[("filename", "a.png"), ("filetype", "image/png")]))), Ok(()));
+
+        assert_eq!(policy.validate(None), Err(MetadataViolation::MissingKey("filename".to_string())));
+
+        let extra = header(&[("filename", "a.png"), ("filetype", "image/png"), ("secret", "x")]);
+        assert_eq!(policy.validate(Some(&extra)), Err(MetadataViolation::KeyNotAllowed("secret".to_string())));
+
+        let long = header(&[("filename", "a-very-very-long-name.png"), ("filetype", "image/png")]);
+        assert!(matches!(policy.validate(Some(&long)), Err(MetadataViolation::ValueTooLong { .. })));
+
+        let file_type = header(&[("filename", "a.png"), ("filetype", "text/html")]);
+        assert_eq!(
+            policy.validate(Some(&file_type)),
+            Err(MetadataViolation::FileTypeNotAllowed("text/html".to_string()))
+        );
+
+        let extension = header(&[("filename", "a.exe"), ("filetype", "image/png")]);
+        assert_eq!(
+            policy.validate(Some(&extension)),
+            Err(MetadataViolation::ExtensionNotAllowed("a.exe".to_string()))
+        );
+
+        let size = MetadataPolicy::new().with_max_header_size(8);
+        assert!(matches!(size.validate(Some(&valid)), Err(MetadataViolation::HeaderTooLarge { .. })));
+    }
+}
@@ -1,3 +1,14 @@
+pub mod extensions;
 pub mod metadata;
+pub mod metadata_policy;
 pub mod file_info;
-pub mod file_store;
\ No newline at end of file
+pub mod file_store;
+pub mod object_file_store;
+pub mod info_store;
+pub mod quota;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite_info_store;
+
+#[cfg(feature = "sqlite")]
+pub mod registry;
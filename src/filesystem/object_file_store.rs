@@ -0,0 +1,638 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use object_store::{
+    multipart::MultipartStore, path::Path, GetOptions, GetRange, ObjectMeta, ObjectStore, PutPayload,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Error, ErrorKind},
+    ops::Range,
+    sync::Arc,
+};
+
+use super::{
+    extensions::FileInfoExtensions,
+    file_info::{Built, Created, FileInfo},
+    file_store::{FileStore, FileStoreError, FileStream, PatchOption},
+    info_store::InfoStore,
+    metadata::Metadata,
+};
+
+/// The smallest part S3, GCS and Azure accept for every part but the last one.
+pub const DEFAULT_MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// The multipart upload in flight for an upload, saved in the `<id>.multipart` sibling object.
+///
+/// Keeps the parts that have already been uploaded, how many bytes are waiting in the
+/// `<id>.part` buffer object, and the offset of the upload they add up to.
+#[derive(Debug, Serialize, Deserialize)]
+struct MultipartState {
+    multipart_id: String,
+    parts: Vec<String>,
+    buffered: usize,
+    // missing from states saved before it was added, which never repair the info.
+    #[serde(default)]
+    offset: u64,
+}
+
+/// Saves every [`FileInfo`] as JSON in the `<prefix>/<id>.info` object.
+pub struct ObjectInfoStore<S> {
+    store: Arc<S>,
+    prefix: Path,
+}
+
+impl<S> Clone for ObjectInfoStore<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+            prefix: self.prefix.clone(),
+        }
+    }
+}
+
+impl<S: ObjectStore> ObjectInfoStore<S> {
+    pub fn new(store: Arc<S>, prefix: &str) -> Self {
+        Self {
+            store,
+            prefix: Path::from(prefix),
+        }
+    }
+
+    fn info_path(&self, file_id: &str) -> Path {
+        self.prefix.child(format!("{}.info", file_id))
+    }
+
+    async fn write_info(&self, file_info: &FileInfo<Created>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let bytes = serde_json::to_vec(file_info)?;
+
+        self.store
+            .put(&self.info_path(file_info.id()), PutPayload::from(bytes))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S: ObjectStore> InfoStore for ObjectInfoStore<S> {
+    async fn create_info(&self, file_info: &FileInfo<Created>) -> Result<(), FileStoreError> {
+        self.write_info(file_info)
+            .await
+            .map_err(FileStoreError::CreationError)
+    }
+
+    async fn get_info(&self, file_id: &str) -> Result<FileInfo<Created>, FileStoreError> {
+        let bytes = read_object(self.store.as_ref(), &self.info_path(file_id)).await?;
+
+        serde_json::from_slice(&bytes).map_err(|e| FileStoreError::ReadError(Box::new(e)))
+    }
+
+    async fn update_info(&self, file_info: &FileInfo<Created>) -> Result<(), FileStoreError> {
+        self.write_info(file_info)
+            .await
+            .map_err(FileStoreError::ReadError)
+    }
+
+    async fn delete_info(&self, file_id: &str) -> Result<(), FileStoreError> {
+        self.store
+            .delete(&self.info_path(file_id))
+            .await
+            .map_err(|e| FileStoreError::TerminationError(Box::new(e)))
+    }
+
+    async fn list_infos(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError> {
+        let objects: Vec<ObjectMeta> = self
+            .store
+            .list(Some(&self.prefix))
+            .try_collect()
+            .await
+            .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+
+        let mut infos = Vec::new();
+        for object in objects {
+            if let Some(file_id) = object.location.filename().and_then(|name| name.strip_suffix(".info")) {
+                infos.push(self.get_info(file_id).await?);
+            }
+        }
+
+        Ok(infos)
+    }
+}
+
+async fn read_object<S: ObjectStore>(store: &S, path: &Path) -> Result<Bytes, FileStoreError> {
+    let object = store
+        .get(path)
+        .await
+        .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+
+    object
+        .bytes()
+        .await
+        .map_err(|e| FileStoreError::ReadError(Box::new(e)))
+}
+
+/// A [`FileStore`] backed by any [`object_store`] implementation that supports multipart uploads
+/// (Amazon S3, Google Cloud Storage, Azure Blob Storage, or `InMemory` for tests). The store has
+/// to implement [`MultipartStore`], which `LocalFileSystem` doesn't (use a [`LocalFileStore`](crate::LocalFileStore)
+/// instead), and the cloud stores are behind the `aws`, `gcp` and `azure` features of `object_store`.
+///
+/// Every upload is written to `<prefix>/<id>` with a multipart upload, and by default its info is
+/// saved as the sibling object `<prefix>/<id>.info` (see [`ObjectInfoStore`]). PATCH data is
+/// uploaded as parts of at least the minimum part size; anything smaller is buffered in
+/// `<prefix>/<id>.part` until the next PATCH (or the final one) comes in.
+///
+/// A PATCH saves its data first, then the `<prefix>/<id>.multipart` state (the uploaded parts and
+/// the offset they add up to), then the info. If the process stops before the info is saved, the
+/// next PATCH on the upload repairs the info from the multipart state and is rejected, so the
+/// client reads the repaired offset with a HEAD request and resumes from there.
+pub struct ObjectFileStore<S, I = ObjectInfoStore<S>> {
+    store: Arc<S>,
+    info_store: I,
+    prefix: Path,
+    min_part_size: usize,
+}
+
+impl<S, I: Clone> Clone for ObjectFileStore<S, I> {
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+            info_store: self.info_store.clone(),
+            prefix: self.prefix.clone(),
+            min_part_size: self.min_part_size,
+        }
+    }
+}
+
+impl<S> ObjectFileStore<S>
+where
+    S: ObjectStore + MultipartStore,
+{
+    pub fn new(store: Arc<S>) -> Self {
+        Self {
+            info_store: ObjectInfoStore::new(Arc::clone(&store), ""),
+            store,
+            prefix: Path::default(),
+            min_part_size: DEFAULT_MIN_PART_SIZE,
+        }
+    }
+
+    /// Stores every upload (and its info object) under the given prefix, e.g. `uploads/` inside
+    /// the bucket.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Path::from(prefix);
+        self.info_store.prefix = Path::from(prefix);
+        self
+    }
+}
+
+impl<S, I> ObjectFileStore<S, I>
+where
+    S: ObjectStore + MultipartStore,
+    I: InfoStore,
+{
+    /// Keeps the upload info in another [`InfoStore`] (e.g. SQLite) instead of sibling objects.
+    pub fn with_info_store<J: InfoStore>(self, info_store: J) -> ObjectFileStore<S, J> {
+        ObjectFileStore {
+            store: self.store,
+            info_store,
+            prefix: self.prefix,
+            min_part_size: self.min_part_size,
+        }
+    }
+
+    /// Overrides the amount of data buffered before a part is uploaded.
+    pub fn with_min_part_size(mut self, min_part_size: usize) -> Self {
+        self.min_part_size = min_part_size;
+        self
+    }
+
+    fn data_path(&self, file_id: &str) -> Path {
+        self.prefix.child(file_id)
+    }
+
+    fn multipart_path(&self, file_id: &str) -> Path {
+        self.prefix.child(format!("{}.multipart", file_id))
+    }
+
+    fn part_path(&self, file_id: &str) -> Path {
+        self.prefix.child(format!("{}.part", file_id))
+    }
+
+    async fn read_multipart(&self, file_id: &str) -> Result<MultipartState, FileStoreError> {
+        let bytes = read_object(self.store.as_ref(), &self.multipart_path(file_id)).await?;
+
+        serde_json::from_slice(&bytes).map_err(|e| FileStoreError::ReadError(Box::new(e)))
+    }
+
+    async fn write_multipart(&self, file_id: &str, multipart: &MultipartState) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let bytes = serde_json::to_vec(multipart)?;
+
+        self.store
+            .put(&self.multipart_path(file_id), PutPayload::from(bytes))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn upload_part(&self, file_id: &str, multipart: &mut MultipartState, data: Vec<u8>) -> Result<(), FileStoreError> {
+        let part = self
+            .store
+            .put_part(
+                &self.data_path(file_id),
+                &multipart.multipart_id,
+                multipart.parts.len(),
+                PutPayload::from(data),
+            )
+            .await
+            .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+
+        multipart.parts.push(part.content_id);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S, I> FileStore for ObjectFileStore<S, I>
+where
+    S: ObjectStore + MultipartStore,
+    I: InfoStore,
+{
+    async fn build_file(
+        &self,
+        length: u64,
+        metadata: Option<&str>,
+    ) -> Result<FileInfo<Built>, FileStoreError> {
+        let metadata = match metadata {
+            Some(metadata) => match Metadata::try_from(metadata) {
+                Ok(m) => m,
+                Err(e) => return Err(FileStoreError::CreationError(Box::new(e))),
+            },
+            None => Metadata::default()
+        };
+
+        let file_info = FileInfo::new(length)
+            .with_uuid()
+            .with_metadata(metadata)
+            .build();
+
+        Ok(file_info)
+    }
+
+    async fn create_file(
+        &self,
+        file_info: FileInfo<Built>,
+    ) -> Result<FileInfo<Created>, FileStoreError> {
+        let data_path = self.data_path(file_info.id());
+
+        let multipart_id = self
+            .store
+            .create_multipart(&data_path)
+            .await
+            .map_err(|e| FileStoreError::CreationError(Box::new(e)))?;
+
+        let multipart = MultipartState {
+            multipart_id,
+            parts: Vec::new(),
+            buffered: 0,
+            offset: 0,
+        };
+
+        let file_info = file_info.mark_as_created(data_path.as_ref());
+
+        self.write_multipart(file_info.id(), &multipart)
+            .await
+            .map_err(FileStoreError::CreationError)?;
+
+        self.info_store.create_info(&file_info).await?;
+        tracing::debug!(upload_id = file_info.id(), length = file_info.length(), path = %data_path, "created upload");
+
+        Ok(file_info)
+    }
+
+    async fn patch_file(
+        &self,
+        file_id: &str,
+        offset: u64,
+        data: &mut [u8],
+    ) -> Result<PatchOption, FileStoreError> {
+        let mut file_info = self.info_store.get_info(file_id).await?;
+        let mut multipart = self.read_multipart(file_id).await?;
+
+        // The last PATCH was saved in the multipart state but not in the info, repair the info so
+        // the client resumes after it.
+        if multipart.offset > *file_info.offset() {
+            tracing::warn!(upload_id = file_id, offset = multipart.offset, "repairing upload info behind its multipart state");
+            file_info
+                .set_offset(multipart.offset)
+                .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+            self.info_store.update_info(&file_info).await?;
+        }
+
+        if *file_info.offset() != offset {
+            return Err(FileStoreError::ReadError(Box::new(
+                Error::from(ErrorKind::InvalidInput),
+            )));
+        }
+
+        file_info
+            .set_offset(offset + data.len() as u64)
+            .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+
+        // The part buffer can hold more than `buffered` bytes if a PATCH died after writing it.
+        let mut buffer = Vec::with_capacity(multipart.buffered + data.len());
+        if multipart.buffered > 0 {
+            let buffered = read_object(self.store.as_ref(), &self.part_path(file_id)).await?;
+            buffer.extend_from_slice(&buffered[..multipart.buffered.min(buffered.len())]);
+        }
+        buffer.extend_from_slice(data);
+
+        let is_complete = file_info.offset() == file_info.length();
+        let had_buffer = multipart.buffered > 0;
+
+        // The last part is allowed to be smaller than the minimum part size.
+        if is_complete || buffer.len() >= self.min_part_size {
+            tracing::debug!(upload_id = file_id, part = multipart.parts.len(), bytes = buffer.len(), "uploading part");
+            self.upload_part(file_id, &mut multipart, buffer).await?;
+            multipart.buffered = 0;
+        } else {
+            multipart.buffered = buffer.len();
+
+            self.store
+                .put(&self.part_path(file_id), PutPayload::from(buffer))
+                .await
+                .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+        }
+
+        if is_complete {
+            let parts = multipart
+                .parts
+                .iter()
+                .map(|content_id| object_store::multipart::PartId {
+                    content_id: content_id.clone(),
+                })
+                .collect();
+
+            self.store
+                .complete_multipart(&self.data_path(file_id), &multipart.multipart_id, parts)
+                .await
+                .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+        }
+
+        // The data is saved before the multipart state, and the multipart state before the info.
+        multipart.offset = *file_info.offset();
+        self.write_multipart(file_id, &multipart)
+            .await
+            .map_err(FileStoreError::ReadError)?;
+
+        self.info_store.update_info(&file_info).await?;
+
+        if had_buffer && multipart.buffered == 0 {
+            self.store
+                .delete(&self.part_path(file_id))
+                .await
+                .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+        }
+
+        let new_offset = *file_info.offset();
+        tracing::debug!(upload_id = file_id, offset, bytes = data.len(), new_offset, "patched upload");
+
+        match file_info.check_completion() {
+            Some(completed) => Ok(PatchOption::Completed(completed)),
+            None => Ok(PatchOption::Patched(new_offset)),
+        }
+    }
+
+    async fn delete_file(
+        &self,
+        file_id: &str,
+    ) -> Result<(), FileStoreError> {
+        let file_info = self.info_store.get_info(file_id).await?;
+        let multipart = self.read_multipart(file_id).await?;
+        let data_path = self.data_path(file_id);
+
+        let result = if file_info.offset() == file_info.length() {
+            self.store.delete(&data_path).await
+        } else {
+            self.store.abort_multipart(&data_path, &multipart.multipart_id).await
+        };
+        result.map_err(|e| FileStoreError::TerminationError(Box::new(e)))?;
+
+        if multipart.buffered > 0 {
+            self.store
+                .delete(&self.part_path(file_id))
+                .await
+                .map_err(|e| FileStoreError::TerminationError(Box::new(e)))?;
+        }
+
+        self.store
+            .delete(&self.multipart_path(file_id))
+            .await
+            .map_err(|e| FileStoreError::TerminationError(Box::new(e)))?;
+
+        self.info_store.delete_info(file_id).await?;
+        tracing::debug!(upload_id = file_id, "deleted upload");
+
+        Ok(())
+    }
+
+    async fn get_file_info(
+        &self,
+        file_id: &str
+    ) -> Result<FileInfo<Created>, FileStoreError> {
+        self.info_store.get_info(file_id).await
+    }
+
+    async fn exists(
+        &self,
+        file_id: &str,
+    ) -> bool {
+        self.store.head(&self.multipart_path(file_id)).await.is_ok()
+    }
+
+    async fn list_files(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError> {
+        self.info_store.list_infos().await
+    }
+
+    async fn set_expiry(
+        &self,
+        file_id: &str,
+        expires_at: Option<u64>,
+    ) -> Result<FileInfo<Created>, FileStoreError> {
+        let mut file_info = self.info_store.get_info(file_id).await?;
+        file_info.set_expiry(expires_at);
+
+        self.info_store.update_info(&file_info).await?;
+
+        Ok(file_info)
+    }
+
+    async fn set_extensions(
+        &self,
+        file_id: &str,
+        extensions: FileInfoExtensions,
+    ) -> Result<FileInfo<Created>, FileStoreError> {
+        let mut file_info = self.info_store.get_info(file_id).await?;
+        file_info.set_extensions(extensions);
+
+        self.info_store.update_info(&file_info).await?;
+
+        Ok(file_info)
+    }
+
+    async fn read_file(
+        &self,
+        file_id: &str,
+        range: Range<u64>,
+    ) -> Result<FileStream, FileStoreError> {
+        let options = GetOptions {
+            range: Some(GetRange::Bounded(range.start as usize..range.end as usize)),
+            ..Default::default()
+        };
+
+        let object = self
+            .store
+            .get_opts(&self.data_path(file_id), options)
+            .await
+            .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+
+        Ok(object
+            .into_stream()
+            .map_err(Error::other)
+            .boxed())
+    }
+
+    /// Smaller writes would be buffered in the `.part` object and read back on every write.
+    fn min_patch_size(&self) -> usize {
+        self.min_part_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+    use object_store::memory::InMemory;
+
+    use super::*;
+    use crate::test_util::{memory_store, MemoryStore};
+
+    async fn create_test_file(store: &MemoryStore, length: u64) -> FileInfo<Created> {
+        let file_name = base64::engine::general_purpose::STANDARD.encode("test_object_file.bin");
+        let metadata = format!("filename {}", file_name);
+
+        let file_info = store.build_file(length, Some(&metadata)).await.unwrap();
+
+        store.create_file(file_info).await.unwrap()
+    }
+
+    async fn read_object(memory: &InMemory, path: &Path) -> Vec<u8> {
+        memory.get(path).await.unwrap().bytes().await.unwrap().to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_patches_are_uploaded_as_parts() {
+        let memory = Arc::new(InMemory::new());
+        let store = ObjectFileStore::new(Arc::clone(&memory))
+            .with_prefix("uploads")
+            .with_min_part_size(4);
+
+        let file_info = create_test_file(&store, 10).await;
+        let id = file_info.id().to_string();
+
+        assert!(store.exists(&id).await);
+
+        // Smaller than the minimum part size, so it stays in the buffer object.
+        match store.patch_file(&id, 0, &mut b"abc".to_vec()).await.unwrap() {
+            PatchOption::Patched(offset) => assert_eq!(offset, 3),
+            PatchOption::Completed(_) => panic!("upload should not be completed"),
+        }
+        assert_eq!(read_object(&memory, &store.part_path(&id)).await, b"abc");
+
+        match store.patch_file(&id, 3, &mut b"defg".to_vec()).await.unwrap() {
+            PatchOption::Patched(offset) => assert_eq!(offset, 7),
+            PatchOption::Completed(_) => panic!("upload should not be completed"),
+        }
+        assert!(memory.head(&store.part_path(&id)).await.is_err());
+
+        match store.patch_file(&id, 7, &mut b"hij".to_vec()).await.unwrap() {
+            PatchOption::Patched(_) => panic!("upload should be completed"),
+            PatchOption::Completed(file_info) => assert_eq!(file_info.length(), &10),
+        }
+
+        assert_eq!(read_object(&memory, &Path::from("uploads").child(id.as_str())).await, b"abcdefghij");
+        assert_eq!(store.read_multipart(&id).await.unwrap().parts.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_patch_with_wrong_offset_is_rejected() {
+        let store = memory_store();
+
+        let file_info = create_test_file(&store, 10).await;
+
+        assert!(store.patch_file(file_info.id(), 5, &mut b"abc".to_vec()).await.is_err());
+        assert_eq!(store.get_file_info(file_info.id()).await.unwrap().offset(), &0);
+    }
+
+    #[tokio::test]
+    async fn test_info_behind_the_multipart_state_is_repaired() {
+        let store = memory_store().with_min_part_size(4);
+        let file_info = create_test_file(&store, 10).await;
+        let id = file_info.id().to_string();
+
+        // the process stopped after saving the multipart state of this PATCH, but not its info
+        store.patch_file(&id, 0, &mut b"abcd".to_vec()).await.unwrap();
+        store.info_store.update_info(&file_info).await.unwrap();
+
+        assert!(store.patch_file(&id, 0, &mut b"abcd".to_vec()).await.is_err());
+        assert_eq!(store.get_file_info(&id).await.unwrap().offset(), &4);
+
+        store.patch_file(&id, 4, &mut b"efghij".to_vec()).await.unwrap();
+        assert_eq!(store.read_multipart(&id).await.unwrap().parts.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_delete_file_removes_all_objects() {
+        let memory = Arc::new(InMemory::new());
+        let store = ObjectFileStore::new(Arc::clone(&memory));
+
+        let file_info = create_test_file(&store, 10).await;
+        let id = file_info.id().to_string();
+
+        store.patch_file(&id, 0, &mut b"abc".to_vec()).await.unwrap();
+        store.delete_file(&id).await.unwrap();
+
+        assert!(!store.exists(&id).await);
+        assert!(memory.head(&store.part_path(&id)).await.is_err());
+        assert!(store.get_file_info(&id).await.is_err());
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Folder(String);
+
+    impl crate::FileInfoExtension for Folder {
+        const KEY: &'static str = "folder";
+    }
+
+    #[tokio::test]
+    async fn test_extensions_are_persisted() {
+        let store = memory_store();
+
+        let mut file_info = store.build_file(3, None).await.unwrap();
+        file_info.extensions_mut().insert(&Folder("inbox".to_string())).unwrap();
+        let id = store.create_file(file_info).await.unwrap().id().to_string();
+
+        let extensions = store.get_file_info(&id).await.unwrap().extensions().clone();
+        assert_eq!(extensions.get::<Folder>().unwrap().unwrap(), Folder("inbox".to_string()));
+
+        let mut extensions = extensions;
+        extensions.insert(&Folder("archive".to_string())).unwrap();
+        store.set_extensions(&id, extensions).await.unwrap();
+
+        // survives patches, which rewrite the info
+        let PatchOption::Completed(file_info) = store.patch_file(&id, 0, &mut b"abc".to_vec()).await.unwrap() else {
+            panic!("upload should be completed");
+        };
+        assert_eq!(file_info.extensions().get::<Folder>().unwrap().unwrap(), Folder("archive".to_string()));
+    }
+}
@@ -0,0 +1,515 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    io::ErrorKind,
+    ops::Range,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    extensions::FileInfoExtensions,
+    file_info::{Built, Created, FileInfo},
+    file_store::{FileStore, FileStoreError, FileStream, PatchOption},
+};
+
+pub type QuotaError = Box<dyn Error + Send + Sync>;
+
+/// Who an upload counts against: the user creating it ([`AuthClaims::get_user_id`](crate::AuthClaims::get_user_id)),
+/// and their tenant ([`AuthClaims::get_tenant_id`](crate::AuthClaims::get_tenant_id)) if any.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaSubject {
+    User(String),
+    Tenant(String),
+}
+
+impl QuotaSubject {
+    fn of<State>(file_info: &FileInfo<State>) -> Vec<Self> {
+        let user = file_info.owner().map(|owner| Self::User(owner.to_string()));
+        let tenant = file_info.tenant().map(|tenant| Self::Tenant(tenant.to_string()));
+
+        user.into_iter().chain(tenant).collect()
+    }
+}
+
+/// The bytes held by a [`QuotaSubject`]; in progress uploads count with their full `Upload-Length`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct QuotaUsage {
+    pub in_progress: u64,
+    pub completed: u64,
+}
+
+impl QuotaUsage {
+    pub fn total(&self) -> u64 {
+        self.in_progress + self.completed
+    }
+}
+
+/// Provides the quota of every user and tenant, and accounts for the uploads held against them.
+#[async_trait]
+pub trait QuotaProvider: Send + Sync + Clone {
+    /// The number of bytes `subject` may hold, `None` if it is unlimited.
+    async fn limit(&self, subject: &QuotaSubject) -> Option<u64>;
+    async fn usage(&self, subject: &QuotaSubject) -> Result<QuotaUsage, QuotaError>;
+    /// Records `length` bytes of the upload against every subject, unless it would exceed one of
+    /// their limits, in which case nothing is recorded and `false` is returned.
+    async fn reserve(&self, upload_id: &str, subjects: &[QuotaSubject], length: u64) -> Result<bool, QuotaError>;
+    async fn complete(&self, upload_id: &str) -> Result<(), QuotaError>;
+    /// Frees the bytes of a terminated or expired upload.
+    async fn release(&self, upload_id: &str) -> Result<(), QuotaError>;
+}
+
+/// Static quota limits: a default per user and per tenant, overridden for specific ids.
+#[derive(Debug, Clone, Default)]
+pub struct QuotaLimits {
+    default_user_limit: Option<u64>,
+    default_tenant_limit: Option<u64>,
+    user_limits: HashMap<String, u64>,
+    tenant_limits: HashMap<String, u64>,
+}
+
+impl QuotaLimits {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_default_user_limit(mut self, limit: u64) -> Self {
+        self.default_user_limit = Some(limit);
+        self
+    }
+
+    pub fn with_default_tenant_limit(mut self, limit: u64) -> Self {
+        self.default_tenant_limit = Some(limit);
+        self
+    }
+
+    pub fn with_user_limit(mut self, user_id: &str, limit: u64) -> Self {
+        self.user_limits.insert(user_id.to_string(), limit);
+        self
+    }
+
+    pub fn with_tenant_limit(mut self, tenant_id: &str, limit: u64) -> Self {
+        self.tenant_limits.insert(tenant_id.to_string(), limit);
+        self
+    }
+
+    pub fn get(&self, subject: &QuotaSubject) -> Option<u64> {
+        match subject {
+            QuotaSubject::User(id) => self.user_limits.get(id).copied().or(self.default_user_limit),
+            QuotaSubject::Tenant(id) => self.tenant_limits.get(id).copied().or(self.default_tenant_limit),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Reservation {
+    subjects: Vec<QuotaSubject>,
+    length: u64,
+    completed: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Ledger {
+    reservations: HashMap<String, Reservation>,
+}
+
+impl Ledger {
+    fn usage(&self, subject: &QuotaSubject) -> QuotaUsage {
+        let mut usage = QuotaUsage::default();
+
+        for reservation in self.reservations.values().filter(|r| r.subjects.contains(subject)) {
+            if reservation.completed {
+                usage.completed += reservation.length;
+            } else {
+                usage.in_progress += reservation.length;
+            }
+        }
+
+        usage
+    }
+
+    fn reserve(&mut self, limits: &QuotaLimits, upload_id: &str, subjects: &[QuotaSubject], length: u64) -> bool {
+        let exceeded = subjects.iter().any(|subject| match limits.get(subject) {
+            Some(limit) => self.usage(subject).total() + length > limit,
+            None => false,
+        });
+
+        if exceeded {
+            return false;
+        }
+
+        self.reservations.insert(upload_id.to_string(), Reservation {
+            subjects: subjects.to_vec(),
+            length,
+            completed: false,
+        });
+
+        true
+    }
+
+    fn complete(&mut self, upload_id: &str) {
+        if let Some(reservation) = self.reservations.get_mut(upload_id) {
+            reservation.completed = true;
+        }
+    }
+
+    fn release(&mut self, upload_id: &str) {
+        self.reservations.remove(upload_id);
+    }
+}
+
+/// Accounts for uploads in memory; usage is lost when the process exits.
+#[derive(Clone, Default)]
+pub struct MemoryQuotaProvider {
+    limits: Arc<QuotaLimits>,
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl MemoryQuotaProvider {
+    pub fn new(limits: QuotaLimits) -> Self {
+        Self {
+            limits: Arc::new(limits),
+            ledger: Default::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl QuotaProvider for MemoryQuotaProvider {
+    async fn limit(&self, subject: &QuotaSubject) -> Option<u64> {
+        self.limits.get(subject)
+    }
+
+    async fn usage(&self, subject: &QuotaSubject) -> Result<QuotaUsage, QuotaError> {
+        Ok(self.ledger.lock().unwrap().usage(subject))
+    }
+
+    async fn reserve(&self, upload_id: &str, subjects: &[QuotaSubject], length: u64) -> Result<bool, QuotaError> {
+        Ok(self.ledger.lock().unwrap().reserve(&self.limits, upload_id, subjects, length))
+    }
+
+    async fn complete(&self, upload_id: &str) -> Result<(), QuotaError> {
+        self.ledger.lock().unwrap().complete(upload_id);
+        Ok(())
+    }
+
+    async fn release(&self, upload_id: &str) -> Result<(), QuotaError> {
+        self.ledger.lock().unwrap().release(upload_id);
+        Ok(())
+    }
+}
+
+/// Accounts for uploads in a JSON file, rewritten after every change, so usage survives restarts.
+#[derive(Clone)]
+pub struct FileQuotaProvider {
+    limits: Arc<QuotaLimits>,
+    path: PathBuf,
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl FileQuotaProvider {
+    /// Opens the accounting file at `path`, starting with no usage if it doesn't exist yet.
+    pub fn open(path: impl Into<PathBuf>, limits: QuotaLimits) -> Result<Self, QuotaError> {
+        let path = path.into();
+
+        let ledger = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Ledger::default(),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        Ok(Self {
+            limits: Arc::new(limits),
+            path,
+            ledger: Arc::new(Mutex::new(ledger)),
+        })
+    }
+
+    fn save(&self, ledger: &Ledger) -> Result<(), QuotaError> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // write a sibling file first, so a crash never leaves a truncated ledger behind.
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(ledger)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl QuotaProvider for FileQuotaProvider {
+    async fn limit(&self, subject: &QuotaSubject) -> Option<u64> {
+        self.limits.get(subject)
+    }
+
+    async fn usage(&self, subject: &QuotaSubject) -> Result<QuotaUsage, QuotaError> {
+        Ok(self.ledger.lock().unwrap().usage(subject))
+    }
+
+    async fn reserve(&self, upload_id: &str, subjects: &[QuotaSubject], length: u64) -> Result<bool, QuotaError> {
+        let mut ledger = self.ledger.lock().unwrap();
+
+        if !ledger.reserve(&self.limits, upload_id, subjects, length) {
+            return Ok(false);
+        }
+
+        if let Err(e) = self.save(&ledger) {
+            ledger.release(upload_id);
+            return Err(e);
+        }
+
+        Ok(true)
+    }
+
+    async fn complete(&self, upload_id: &str) -> Result<(), QuotaError> {
+        let mut ledger = self.ledger.lock().unwrap();
+        ledger.complete(upload_id);
+
+        self.save(&ledger)
+    }
+
+    async fn release(&self, upload_id: &str) -> Result<(), QuotaError> {
+        let mut ledger = self.ledger.lock().unwrap();
+        ledger.release(upload_id);
+
+        self.save(&ledger)
+    }
+}
+
+/// Wrap a [`FileStore`] in a [`QuotaFileStore`] to enforce the quotas of `Q`.
+///
+/// Uploads are counted against their owner and tenant when created (rejected with
+/// [`FileStoreError::QuotaExceeded`] if that would exceed a quota), and freed when terminated
+/// or removed by [`FileStore::remove_expired_files`]. Uploads created without an owner are not
+/// counted.
+#[derive(Clone)]
+pub struct QuotaFileStore<T: FileStore, Q: QuotaProvider> {
+    file_store: T,
+    quotas: Q,
+}
+
+impl<T: FileStore, Q: QuotaProvider> QuotaFileStore<T, Q> {
+    pub fn new(file_store: T, quotas: Q) -> Self {
+        Self {
+            file_store,
+            quotas,
+        }
+    }
+
+    pub fn quotas(&self) -> &Q {
+        &self.quotas
+    }
+}
+
+#[async_trait]
+impl<T: FileStore, Q: QuotaProvider> FileStore for QuotaFileStore<T, Q> {
+    async fn build_file(&self, length: u64, metadata: Option<&str>) -> Result<FileInfo<Built>, FileStoreError> {
+        self.file_store.build_file(length, metadata).await
+    }
+
+    async fn create_file(&self, file_info: FileInfo<Built>) -> Result<FileInfo<Created>, FileStoreError> {
+        let subjects = QuotaSubject::of(&file_info);
+        if subjects.is_empty() {
+            return self.file_store.create_file(file_info).await;
+        }
+
+        // reserved before creating, so concurrent uploads can't both fit in the same room
+        let id = file_info.id().to_string();
+        match self.quotas.reserve(&id, &subjects, *file_info.length()).await {
+            Ok(true) => {},
+            Ok(false) => return Err(FileStoreError::QuotaExceeded),
+            Err(e) => {
+                tracing::error!(upload_id = id.as_str(), error = %e, "could not reserve quota");
+                return Err(FileStoreError::CreationError(e));
+            },
+        }
+
+        let created = self.file_store.create_file(file_info).await;
+
+        if created.is_err() {
+            if let Err(e) = self.quotas.release(&id).await {
+                tracing::error!(upload_id = id.as_str(), error = %e, "could not release quota of failed upload");
+            }
+        }
+
+        created
+    }
+
+    async fn patch_file(&self, file_id: &str, offset: u64, data: &mut [u8]) -> Result<PatchOption, FileStoreError> {
+        let patch = self.file_store.patch_file(file_id, offset, data).await?;
+
+        if let PatchOption::Completed(_) = &patch {
+            if let Err(e) = self.quotas.complete(file_id).await {
+                tracing::error!(upload_id = file_id, error = %e, "could not complete upload in the quotas");
+            }
+        }
+
+        Ok(patch)
+    }
+
+    async fn delete_file(&self, file_id: &str) -> Result<(), FileStoreError> {
+        self.file_store.delete_file(file_id).await?;
+
+        if let Err(e) = self.quotas.release(file_id).await {
+            tracing::error!(upload_id = file_id, error = %e, "could not release upload from the quotas");
+        }
+
+        Ok(())
+    }
+
+    async fn get_file_info(&self, file_id: &str) -> Result<FileInfo<Created>, FileStoreError> {
+        self.file_store.get_file_info(file_id).await
+    }
+
+    async fn exists(&self, file_id: &str) -> bool {
+        self.file_store.exists(file_id).await
+    }
+
+    async fn list_files(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError> {
+        self.file_store.list_files().await
+    }
+
+    async fn set_expiry(&self, file_id: &str, expires_at: Option<u64>) -> Result<FileInfo<Created>, FileStoreError> {
+        self.file_store.set_expiry(file_id, expires_at).await
+    }
+
+    async fn set_extensions(
+        &self,
+        file_id: &str,
+        extensions: FileInfoExtensions,
+    ) -> Result<FileInfo<Created>, FileStoreError> {
+        self.file_store.set_extensions(file_id, extensions).await
+    }
+
+    async fn read_file(&self, file_id: &str, range: Range<u64>) -> Result<FileStream, FileStoreError> {
+        self.file_store.read_file(file_id, range).await
+    }
+
+    fn min_patch_size(&self) -> usize {
+        self.file_store.min_patch_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::file_info::unix_timestamp;
+    use crate::test_util::{memory_store, MemoryStore};
+
+    fn quota_store() -> QuotaFileStore<MemoryStore, MemoryQuotaProvider> {
+        let limits = QuotaLimits::new()
+            .with_default_user_limit(10)
+            .with_tenant_limit("acme", 15);
+
+        QuotaFileStore::new(memory_store(), MemoryQuotaProvider::new(limits))
+    }
+
+    async fn create_upload<Q: QuotaProvider>(
+        store: &QuotaFileStore<MemoryStore, Q>,
+        length: u64,
+        owner: &str,
+        tenant: &str,
+    ) -> Result<String, FileStoreError> {
+        let file_info = store
+            .build_file(length, None)
+            .await?
+            .with_owner(owner)
+            .with_tenant(Some(tenant));
+
+        Ok(store.create_file(file_info).await?.id().to_string())
+    }
+
+    async fn tenant_usage<Q: QuotaProvider>(store: &QuotaFileStore<MemoryStore, Q>) -> QuotaUsage {
+        store.quotas().usage(&QuotaSubject::Tenant("acme".to_string())).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_user_quotas_are_enforced() {
+        let store = quota_store();
+
+        create_upload(&store, 8, "alice", "acme").await.unwrap();
+        assert!(matches!(create_upload(&store, 4, "alice", "acme").await, Err(FileStoreError::QuotaExceeded)));
+        assert_eq!(store.list_files().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_quotas_are_enforced() {
+        let store = quota_store();
+        create_upload(&store, 8, "alice", "acme").await.unwrap();
+
+        // bob has room, but the acme tenant does not.
+        assert!(matches!(create_upload(&store, 8, "bob", "acme").await, Err(FileStoreError::QuotaExceeded)));
+        create_upload(&store, 7, "bob", "acme").await.unwrap();
+        assert_eq!(tenant_usage(&store).await.total(), 15);
+    }
+
+    #[tokio::test]
+    async fn test_completed_uploads_are_counted_as_completed() {
+        let store = quota_store();
+        let first = create_upload(&store, 8, "alice", "acme").await.unwrap();
+        create_upload(&store, 7, "bob", "acme").await.unwrap();
+
+        store.patch_file(&first, 0, &mut [0; 8]).await.unwrap();
+        assert_eq!(tenant_usage(&store).await, QuotaUsage { in_progress: 7, completed: 8 });
+    }
+
+    #[tokio::test]
+    async fn test_terminated_and_expired_uploads_are_freed() {
+        let store = quota_store();
+        let first = create_upload(&store, 8, "alice", "acme").await.unwrap();
+        let second = create_upload(&store, 7, "bob", "acme").await.unwrap();
+
+        store.delete_file(&first).await.unwrap();
+        store.set_expiry(&second, Some(unix_timestamp() - 1)).await.unwrap();
+        let removed = store.remove_expired_files().await.unwrap();
+        assert_eq!(removed.iter().map(|file_info| file_info.id()).collect::<Vec<_>>(), [second.as_str()]);
+
+        assert_eq!(tenant_usage(&store).await.total(), 0);
+        create_upload(&store, 10, "alice", "acme").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_quota_is_released_when_creation_fails() {
+        // uploads can't be created under a file
+        let root = std::env::temp_dir().join(format!("axum-tus-quota-root-{}", uuid::Uuid::new_v4()));
+        fs::write(&root, b"").unwrap();
+
+        let limits = QuotaLimits::new().with_default_user_limit(10);
+        let store = QuotaFileStore::new(
+            crate::LocalFileStore::new(root.to_str().unwrap().to_string()),
+            MemoryQuotaProvider::new(limits),
+        );
+
+        let file_info = store.build_file(8, None).await.unwrap().with_owner("alice");
+        assert!(matches!(store.create_file(file_info).await, Err(FileStoreError::CreationError(_))));
+
+        let usage = store.quotas().usage(&QuotaSubject::User("alice".to_string())).await.unwrap();
+        assert_eq!(usage.total(), 0);
+
+        fs::remove_file(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_quotas_are_persisted() {
+        let path = std::env::temp_dir().join(format!("axum-tus-quotas-{}.json", uuid::Uuid::new_v4()));
+        let limits = QuotaLimits::new().with_user_limit("alice", 10);
+
+        let quotas = FileQuotaProvider::open(&path, limits.clone()).unwrap();
+        let store = QuotaFileStore::new(memory_store(), quotas);
+        create_upload(&store, 6, "alice", "acme").await.unwrap();
+
+        let reopened = FileQuotaProvider::open(&path, limits).unwrap();
+        let usage = reopened.usage(&QuotaSubject::User("alice".to_string())).await.unwrap();
+        assert_eq!(usage.in_progress, 6);
+        assert!(!reopened.reserve("other", &[QuotaSubject::User("alice".to_string())], 5).await.unwrap());
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,581 @@
+use async_trait::async_trait;
+use rusqlite::{params, params_from_iter, types::Value, Connection};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    ops::Range,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{
+    extensions::FileInfoExtensions,
+    file_info::{unix_timestamp, Built, Created, FileInfo},
+    file_store::{FileStore, FileStoreError, FileStream, PatchOption},
+    sqlite_info_store::run_blocking,
+};
+
+pub type RegistryError = Box<dyn Error + Send + Sync>;
+
+/// The transitions an upload goes through, as recorded by the [`UploadRegistry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadState {
+    Created,
+    Patched,
+    Completed,
+    Terminated,
+}
+
+impl UploadState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Patched => "patched",
+            Self::Completed => "completed",
+            Self::Terminated => "terminated",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "created" => Some(Self::Created),
+            "patched" => Some(Self::Patched),
+            "completed" => Some(Self::Completed),
+            "terminated" => Some(Self::Terminated),
+            _ => None,
+        }
+    }
+}
+
+/// The latest known state of an upload.
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadRecord {
+    pub file_info: FileInfo<Created>,
+    pub state: UploadState,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// One entry of the transition log of an upload.
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadTransition {
+    pub state: UploadState,
+    pub offset: u64,
+    pub recorded_at: u64,
+}
+
+/// Filters for [`UploadRegistry::query`]; every filter left unset matches all uploads.
+#[derive(Debug, Clone, Default)]
+pub struct UploadQuery {
+    state: Option<UploadState>,
+    owner: Option<String>,
+    metadata_key: Option<String>,
+    metadata_value: Option<String>,
+    created_before: Option<u64>,
+    created_after: Option<u64>,
+    limit: Option<u64>,
+    offset: u64,
+}
+
+impl UploadQuery {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_state(mut self, state: UploadState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    pub fn with_owner(mut self, owner: &str) -> Self {
+        self.owner = Some(owner.to_string());
+        self
+    }
+
+    /// Only matches uploads whose `Upload-Metadata` contains `key`.
+    pub fn with_metadata_key(mut self, key: &str) -> Self {
+        self.metadata_key = Some(key.to_string());
+        self
+    }
+
+    /// Only matches uploads whose `Upload-Metadata` contains `key` with the given (decoded) value.
+    pub fn with_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata_key = Some(key.to_string());
+        self.metadata_value = Some(value.to_string());
+        self
+    }
+
+    /// Only matches uploads created at least `age` ago.
+    pub fn older_than(mut self, age: Duration) -> Self {
+        self.created_before = Some(unix_timestamp().saturating_sub(age.as_secs()));
+        self
+    }
+
+    /// Only matches uploads created before the given unix timestamp.
+    pub fn created_before(mut self, timestamp: u64) -> Self {
+        self.created_before = Some(timestamp);
+        self
+    }
+
+    /// Only matches uploads created at or after the given unix timestamp.
+    pub fn created_after(mut self, timestamp: u64) -> Self {
+        self.created_after = Some(timestamp);
+        self
+    }
+
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    fn where_clause(&self) -> (String, Vec<Value>) {
+        let mut clause = String::from("WHERE 1 = 1");
+        let mut values = Vec::new();
+
+        if let Some(state) = self.state {
+            clause.push_str(" AND state = ?");
+            values.push(Value::Text(state.name().to_string()));
+        }
+
+        if let Some(owner) = &self.owner {
+            clause.push_str(" AND owner = ?");
+            values.push(Value::Text(owner.clone()));
+        }
+
+        if let Some(created_before) = self.created_before {
+            clause.push_str(" AND created_at < ?");
+            values.push(Value::Integer(created_before as i64));
+        }
+
+        if let Some(created_after) = self.created_after {
+            clause.push_str(" AND created_at >= ?");
+            values.push(Value::Integer(created_after as i64));
+        }
+
+        if let Some(key) = &self.metadata_key {
+            clause.push_str(" AND EXISTS (SELECT 1 FROM upload_metadata m WHERE m.upload_id = uploads.id AND m.key = ?");
+            values.push(Value::Text(key.clone()));
+
+            if let Some(value) = &self.metadata_value {
+                clause.push_str(" AND m.value = ?");
+                values.push(Value::Text(value.clone()));
+            }
+            clause.push(')');
+        }
+
+        (clause, values)
+    }
+}
+
+/// A SQLite log of every upload and its transitions, to list and query uploads (by state, age,
+/// owner or metadata key) for dashboards, cleanup jobs and support.
+///
+/// Wrap a [`FileStore`] in a [`RegistryFileStore`] to record its transitions automatically.
+#[derive(Clone)]
+pub struct UploadRegistry {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl UploadRegistry {
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> rusqlite::Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS uploads (
+                id TEXT PRIMARY KEY,
+                state TEXT NOT NULL,
+                length INTEGER NOT NULL,
+                offset INTEGER NOT NULL,
+                owner TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                info TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS uploads_state ON uploads (state);
+            CREATE INDEX IF NOT EXISTS uploads_owner ON uploads (owner);
+            CREATE INDEX IF NOT EXISTS uploads_created_at ON uploads (created_at);
+            CREATE TABLE IF NOT EXISTS upload_metadata (
+                upload_id TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT,
+                PRIMARY KEY (upload_id, key)
+            );
+            CREATE INDEX IF NOT EXISTS upload_metadata_key ON upload_metadata (key, value);
+            CREATE TABLE IF NOT EXISTS upload_transitions (
+                upload_id TEXT NOT NULL,
+                state TEXT NOT NULL,
+                offset INTEGER NOT NULL,
+                recorded_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS upload_transitions_upload_id ON upload_transitions (upload_id);",
+        )?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Records that the upload moved to `state`, updating its latest known info.
+    pub async fn record<State>(&self, file_info: &FileInfo<State>, state: UploadState) -> Result<(), RegistryError> {
+        let info = serde_json::to_string(file_info)?;
+        let id = file_info.id().to_string();
+        let length = *file_info.length();
+        let offset = *file_info.offset();
+        let owner = file_info.owner().map(String::from);
+        let metadata: Vec<(String, Option<String>)> = match file_info.metadata() {
+            Some(metadata) => metadata
+                .keys()
+                .map(|key| (key.clone(), metadata.try_get_key(key)))
+                .collect(),
+            None => Vec::new(),
+        };
+        let now = unix_timestamp();
+
+        run_blocking(&self.connection, move |connection| {
+            let transaction = connection.unchecked_transaction()?;
+
+            transaction.execute(
+                "INSERT INTO uploads (id, state, length, offset, owner, created_at, updated_at, info)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?7)
+                 ON CONFLICT (id) DO UPDATE SET
+                    state = excluded.state,
+                    length = excluded.length,
+                    offset = excluded.offset,
+                    owner = excluded.owner,
+                    updated_at = excluded.updated_at,
+                    info = excluded.info",
+                params![id, state.name(), length, offset, owner, now, info],
+            )?;
+
+            for (key, value) in metadata {
+                transaction.execute(
+                    "INSERT OR REPLACE INTO upload_metadata (upload_id, key, value) VALUES (?1, ?2, ?3)",
+                    params![id, key, value],
+                )?;
+            }
+
+            transaction.execute(
+                "INSERT INTO upload_transitions (upload_id, state, offset, recorded_at) VALUES (?1, ?2, ?3, ?4)",
+                params![id, state.name(), offset, now],
+            )?;
+
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Refreshes the latest known info of an upload without recording a transition.
+    pub async fn update<State>(&self, file_info: &FileInfo<State>) -> Result<(), RegistryError> {
+        let info = serde_json::to_string(file_info)?;
+        let id = file_info.id().to_string();
+        let now = unix_timestamp();
+
+        run_blocking(&self.connection, move |connection| {
+            connection.execute(
+                "UPDATE uploads SET updated_at = ?2, info = ?3 WHERE id = ?1",
+                params![id, now, info],
+            )
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(&self, file_id: &str) -> Result<Option<UploadRecord>, RegistryError> {
+        let file_id = file_id.to_string();
+
+        let records = self
+            .select(move |connection| {
+                let mut statement = connection.prepare(
+                    "SELECT info, state, created_at, updated_at FROM uploads WHERE id = ?1",
+                )?;
+                let rows = statement.query_map(params![file_id], read_row)?;
+                rows.collect()
+            })
+            .await?;
+
+        Ok(records.into_iter().next())
+    }
+
+    /// Lists the uploads matching `query`, oldest first.
+    pub async fn query(&self, query: &UploadQuery) -> Result<Vec<UploadRecord>, RegistryError> {
+        let (clause, mut values) = query.where_clause();
+        values.push(Value::Integer(query.limit.map(|limit| limit as i64).unwrap_or(-1)));
+        values.push(Value::Integer(query.offset as i64));
+
+        self.select(move |connection| {
+            let mut statement = connection.prepare(&format!(
+                "SELECT info, state, created_at, updated_at FROM uploads {} ORDER BY created_at, id LIMIT ? OFFSET ?",
+                clause
+            ))?;
+            let rows = statement.query_map(params_from_iter(values), read_row)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Counts the uploads matching `query`, ignoring its limit and offset.
+    pub async fn count(&self, query: &UploadQuery) -> Result<u64, RegistryError> {
+        let (clause, values) = query.where_clause();
+
+        run_blocking(&self.connection, move |connection| {
+            connection.query_row(
+                &format!("SELECT COUNT(*) FROM uploads {}", clause),
+                params_from_iter(values),
+                |row| row.get(0),
+            )
+        })
+        .await
+    }
+
+    /// Every transition recorded for an upload, in order.
+    pub async fn transitions(&self, file_id: &str) -> Result<Vec<UploadTransition>, RegistryError> {
+        let file_id = file_id.to_string();
+
+        let transitions: Vec<(String, u64, u64)> = run_blocking(&self.connection, move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT state, offset, recorded_at FROM upload_transitions WHERE upload_id = ?1 ORDER BY rowid",
+            )?;
+            let rows = statement.query_map(params![file_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect()
+        })
+        .await?;
+
+        transitions
+            .into_iter()
+            .map(|(state, offset, recorded_at)| {
+                Ok(UploadTransition {
+                    state: parse_state(&state)?,
+                    offset,
+                    recorded_at,
+                })
+            })
+            .collect()
+    }
+
+    async fn select<F>(&self, query: F) -> Result<Vec<UploadRecord>, RegistryError>
+    where
+        F: FnOnce(&Connection) -> rusqlite::Result<Vec<(String, String, u64, u64)>> + Send + 'static,
+    {
+        run_blocking(&self.connection, query)
+            .await?
+            .into_iter()
+            .map(|(info, state, created_at, updated_at)| {
+                Ok(UploadRecord {
+                    file_info: serde_json::from_str(&info)?,
+                    state: parse_state(&state)?,
+                    created_at,
+                    updated_at,
+                })
+            })
+            .collect()
+    }
+}
+
+fn read_row(row: &rusqlite::Row) -> rusqlite::Result<(String, String, u64, u64)> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+}
+
+fn parse_state(state: &str) -> Result<UploadState, RegistryError> {
+    UploadState::from_name(state).ok_or_else(|| format!("unknown upload state: {}", state).into())
+}
+
+/// A [`FileStore`] that records every transition of the wrapped store in an [`UploadRegistry`].
+///
+/// Failing to record a transition is logged and never fails the upload itself.
+#[derive(Clone)]
+pub struct RegistryFileStore<T: FileStore> {
+    file_store: T,
+    registry: UploadRegistry,
+}
+
+impl<T: FileStore> RegistryFileStore<T> {
+    pub fn new(file_store: T, registry: UploadRegistry) -> Self {
+        Self {
+            file_store,
+            registry,
+        }
+    }
+
+    pub fn registry(&self) -> &UploadRegistry {
+        &self.registry
+    }
+
+    async fn record<State>(&self, file_info: &FileInfo<State>, state: UploadState) {
+        if let Err(e) = self.registry.record(file_info, state).await {
+            tracing::error!(upload_id = file_info.id(), state = state.name(), error = %e, "could not record upload in the registry");
+        }
+    }
+}
+
+#[async_trait]
+impl<T: FileStore> FileStore for RegistryFileStore<T> {
+    async fn build_file(&self, length: u64, metadata: Option<&str>) -> Result<FileInfo<Built>, FileStoreError> {
+        self.file_store.build_file(length, metadata).await
+    }
+
+    async fn create_file(&self, file_info: FileInfo<Built>) -> Result<FileInfo<Created>, FileStoreError> {
+        let file_info = self.file_store.create_file(file_info).await?;
+
+        self.record(&file_info, UploadState::Created).await;
+
+        Ok(file_info)
+    }
+
+    async fn patch_file(&self, file_id: &str, offset: u64, data: &mut [u8]) -> Result<PatchOption, FileStoreError> {
+        let patch = self.file_store.patch_file(file_id, offset, data).await?;
+
+        match &patch {
+            PatchOption::Patched(_) => {
+                let file_info = self.file_store.get_file_info(file_id).await.ok();
+
+                if let Some(file_info) = file_info {
+                    self.record(&file_info, UploadState::Patched).await;
+                }
+            },
+            PatchOption::Completed(file_info) => self.record(file_info, UploadState::Completed).await,
+        }
+
+        Ok(patch)
+    }
+
+    async fn delete_file(&self, file_id: &str) -> Result<(), FileStoreError> {
+        let file_info = self.file_store.get_file_info(file_id).await.ok();
+
+        self.file_store.delete_file(file_id).await?;
+
+        if let Some(file_info) = file_info {
+            self.record(&file_info, UploadState::Terminated).await;
+        }
+
+        Ok(())
+    }
+
+    async fn get_file_info(&self, file_id: &str) -> Result<FileInfo<Created>, FileStoreError> {
+        self.file_store.get_file_info(file_id).await
+    }
+
+    async fn exists(&self, file_id: &str) -> bool {
+        self.file_store.exists(file_id).await
+    }
+
+    async fn list_files(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError> {
+        self.file_store.list_files().await
+    }
+
+    async fn set_expiry(&self, file_id: &str, expires_at: Option<u64>) -> Result<FileInfo<Created>, FileStoreError> {
+        let file_info = self.file_store.set_expiry(file_id, expires_at).await?;
+
+        if let Err(e) = self.registry.update(&file_info).await {
+            tracing::error!(upload_id = file_info.id(), error = %e, "could not update upload in the registry");
+        }
+
+        Ok(file_info)
+    }
+
+    async fn set_extensions(
+        &self,
+        file_id: &str,
+        extensions: FileInfoExtensions,
+    ) -> Result<FileInfo<Created>, FileStoreError> {
+        let file_info = self.file_store.set_extensions(file_id, extensions).await?;
+
+        if let Err(e) = self.registry.update(&file_info).await {
+            tracing::error!(upload_id = file_info.id(), error = %e, "could not update upload in the registry");
+        }
+
+        Ok(file_info)
+    }
+
+    async fn read_file(&self, file_id: &str, range: Range<u64>) -> Result<FileStream, FileStoreError> {
+        self.file_store.read_file(file_id, range).await
+    }
+
+    fn min_patch_size(&self) -> usize {
+        self.file_store.min_patch_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+
+    use super::*;
+    use crate::test_util::{memory_store, MemoryStore};
+
+    async fn create_project_upload(store: &RegistryFileStore<MemoryStore>, length: u64, project: &str) -> String {
+        let project = base64::engine::general_purpose::STANDARD.encode(project);
+        let metadata = format!("project {}", project);
+
+        let file_info = store.build_file(length, Some(&metadata)).await.unwrap();
+
+        store.create_file(file_info).await.unwrap().id().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_transitions_are_recorded() {
+        let store = RegistryFileStore::new(memory_store(), UploadRegistry::open_in_memory().unwrap());
+
+        let id = create_project_upload(&store, 4, "alpha").await;
+
+        store.patch_file(&id, 0, &mut b"ab".to_vec()).await.unwrap();
+        store.patch_file(&id, 2, &mut b"cd".to_vec()).await.unwrap();
+
+        let record = store.registry().get(&id).await.unwrap().unwrap();
+        assert_eq!(record.state, UploadState::Completed);
+        assert_eq!(record.file_info.offset(), &4);
+
+        let states: Vec<UploadState> = store
+            .registry()
+            .transitions(&id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|transition| transition.state)
+            .collect();
+        assert_eq!(states, vec![UploadState::Created, UploadState::Patched, UploadState::Completed]);
+    }
+
+    #[tokio::test]
+    async fn test_query_uploads() {
+        let store = RegistryFileStore::new(memory_store(), UploadRegistry::open_in_memory().unwrap());
+
+        let alpha = create_project_upload(&store, 4, "alpha").await;
+        let beta = create_project_upload(&store, 4, "beta").await;
+        let gamma = create_project_upload(&store, 4, "beta").await;
+
+        store.delete_file(&gamma).await.unwrap();
+
+        let registry = store.registry();
+
+        let created = registry.query(&UploadQuery::new().with_state(UploadState::Created)).await.unwrap();
+        let mut created: Vec<&str> = created.iter().map(|record| record.file_info.id()).collect();
+        created.sort();
+        let mut expected = vec![alpha.as_str(), beta.as_str()];
+        expected.sort();
+        assert_eq!(created, expected);
+
+        let terminated = registry.query(&UploadQuery::new().with_state(UploadState::Terminated)).await.unwrap();
+        assert_eq!(terminated.len(), 1);
+        assert_eq!(terminated[0].file_info.id(), gamma);
+
+        assert_eq!(registry.count(&UploadQuery::new().with_metadata("project", "beta")).await.unwrap(), 2);
+        assert_eq!(registry.count(&UploadQuery::new().with_metadata_key("project")).await.unwrap(), 3);
+        assert_eq!(registry.count(&UploadQuery::new().with_metadata_key("filename")).await.unwrap(), 0);
+        assert_eq!(registry.count(&UploadQuery::new().older_than(Duration::from_secs(3600))).await.unwrap(), 0);
+        assert_eq!(registry.query(&UploadQuery::new().with_limit(2)).await.unwrap().len(), 2);
+    }
+}
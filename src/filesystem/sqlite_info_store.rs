@@ -0,0 +1,244 @@
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{
+    error::Error,
+    io::ErrorKind,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    file_info::{Created, FileInfo},
+    file_store::FileStoreError,
+    info_store::InfoStore,
+};
+
+/// Saves every [`FileInfo`] in a SQLite table.
+///
+/// The whole info is kept as JSON, while the offset, length, owner and expiry get their own
+/// (indexed) columns so uploads can be queried without deserializing every row.
+#[derive(Clone)]
+pub struct SqliteInfoStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteInfoStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> rusqlite::Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS upload_info (
+                id TEXT PRIMARY KEY,
+                length INTEGER NOT NULL,
+                offset INTEGER NOT NULL,
+                owner TEXT,
+                expires_at INTEGER,
+                info TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS upload_info_owner ON upload_info (owner);
+            CREATE INDEX IF NOT EXISTS upload_info_expires_at ON upload_info (expires_at);",
+        )?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    async fn call<T, F>(&self, query: F) -> Result<T, Box<dyn Error + Send + Sync>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        run_blocking(&self.connection, query).await.map_err(|e| e as Box<dyn Error + Send + Sync>)
+    }
+}
+
+/// Runs a query on the blocking thread pool, since rusqlite is synchronous.
+pub(super) async fn run_blocking<T, F>(
+    connection: &Arc<Mutex<Connection>>,
+    query: F,
+) -> Result<T, Box<dyn Error + Send + Sync>>
+where
+    T: Send + 'static,
+    F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+{
+    let connection = Arc::clone(connection);
+
+    match tokio::task::spawn_blocking(move || query(&connection.lock().unwrap())).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(Box::new(e)),
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+#[async_trait]
+impl InfoStore for SqliteInfoStore {
+    async fn create_info(&self, file_info: &FileInfo<Created>) -> Result<(), FileStoreError> {
+        let info = serde_json::to_string(file_info)
+            .map_err(|e| FileStoreError::CreationError(Box::new(e)))?;
+        let file_info = file_info.clone();
+
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT INTO upload_info (id, length, offset, owner, expires_at, info)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    file_info.id(),
+                    file_info.length(),
+                    file_info.offset(),
+                    file_info.owner(),
+                    file_info.expires_at(),
+                    info
+                ],
+            )
+        })
+        .await
+        .map_err(FileStoreError::CreationError)?;
+
+        Ok(())
+    }
+
+    async fn get_info(&self, file_id: &str) -> Result<FileInfo<Created>, FileStoreError> {
+        let file_id = file_id.to_string();
+
+        let info: Option<String> = self
+            .call(move |connection| {
+                connection
+                    .query_row(
+                        "SELECT info FROM upload_info WHERE id = ?1",
+                        params![file_id],
+                        |row| row.get(0),
+                    )
+                    .optional()
+            })
+            .await
+            .map_err(FileStoreError::ReadError)?;
+
+        match info {
+            Some(info) => serde_json::from_str(&info).map_err(|e| FileStoreError::ReadError(Box::new(e))),
+            None => Err(FileStoreError::ReadError(Box::new(
+                std::io::Error::from(ErrorKind::NotFound),
+            ))),
+        }
+    }
+
+    async fn update_info(&self, file_info: &FileInfo<Created>) -> Result<(), FileStoreError> {
+        let info = serde_json::to_string(file_info)
+            .map_err(|e| FileStoreError::ReadError(Box::new(e)))?;
+        let file_info = file_info.clone();
+
+        let updated = self
+            .call(move |connection| {
+                connection.execute(
+                    "UPDATE upload_info SET length = ?2, offset = ?3, owner = ?4, expires_at = ?5, info = ?6
+                     WHERE id = ?1",
+                    params![
+                        file_info.id(),
+                        file_info.length(),
+                        file_info.offset(),
+                        file_info.owner(),
+                        file_info.expires_at(),
+                        info
+                    ],
+                )
+            })
+            .await
+            .map_err(FileStoreError::ReadError)?;
+
+        if updated == 0 {
+            return Err(FileStoreError::ReadError(Box::new(
+                std::io::Error::from(ErrorKind::NotFound),
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_info(&self, file_id: &str) -> Result<(), FileStoreError> {
+        let file_id = file_id.to_string();
+
+        let deleted = self
+            .call(move |connection| {
+                connection.execute("DELETE FROM upload_info WHERE id = ?1", params![file_id])
+            })
+            .await
+            .map_err(FileStoreError::TerminationError)?;
+
+        if deleted == 0 {
+            return Err(FileStoreError::TerminationError(Box::new(
+                std::io::Error::from(ErrorKind::NotFound),
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn list_infos(&self) -> Result<Vec<FileInfo<Created>>, FileStoreError> {
+        let infos: Vec<String> = self
+            .call(|connection| {
+                let mut statement = connection.prepare("SELECT info FROM upload_info ORDER BY id")?;
+                let rows = statement.query_map([], |row| row.get(0))?;
+                rows.collect()
+            })
+            .await
+            .map_err(FileStoreError::ReadError)?;
+
+        infos
+            .iter()
+            .map(|info| serde_json::from_str(info).map_err(|e| FileStoreError::ReadError(Box::new(e))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::file_store::{FileStore, LocalFileStore, PatchOption};
+    use crate::test_util::{create_upload, memory_store};
+
+    #[tokio::test]
+    async fn test_object_store_with_sqlite_info() {
+        let info_store = SqliteInfoStore::open_in_memory().unwrap();
+        let store = memory_store().with_info_store(info_store.clone());
+
+        let file_info = create_upload(&store, 6).await;
+        let file_id = file_info.id().to_string();
+
+        match store.patch_file(&file_id, 0, &mut b"abc".to_vec()).await.unwrap() {
+            PatchOption::Patched(offset) => assert_eq!(offset, 3),
+            PatchOption::Completed(_) => panic!("upload should not be completed"),
+        }
+
+        assert_eq!(info_store.get_info(&file_id).await.unwrap().offset(), &3);
+
+        store.delete_file(&file_id).await.unwrap();
+        assert!(info_store.get_info(&file_id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_info_is_persisted() {
+        let root_path = std::env::temp_dir().join("axum_tus_sqlite_info_store");
+        let _ = std::fs::remove_dir_all(&root_path);
+        std::fs::create_dir_all(&root_path).unwrap();
+
+        let database = root_path.join("uploads.db");
+        let store = LocalFileStore::new(root_path.to_str().unwrap().to_string())
+            .with_info_store(SqliteInfoStore::open(&database).unwrap());
+
+        let file_info = create_upload(&store, 4).await;
+
+        store.patch_file(file_info.id(), 0, &mut b"ab".to_vec()).await.unwrap();
+
+        let reopened = SqliteInfoStore::open(&database).unwrap();
+        let file_info = reopened.get_info(file_info.id()).await.unwrap();
+
+        assert_eq!(file_info.offset(), &2);
+        assert_eq!(file_info.length(), &4);
+    }
+}
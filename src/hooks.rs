@@ -0,0 +1,106 @@
+use async_trait::async_trait;
+use http::StatusCode;
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use crate::{
+    filesystem::file_info::{Created, FileInfo},
+    RequestContext, UploadEvent,
+};
+
+/// The `type` of the payload sent to [`PreCreateHook`]s.
+pub const PRE_CREATE: &str = "pre-create";
+
+/// The request an upload event was caused by, as sent to hooks.
+#[derive(Debug, Clone, Serialize)]
+pub struct HookRequest {
+    pub method: String,
+    pub uri: String,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl From<&RequestContext> for HookRequest {
+    fn from(context: &RequestContext) -> Self {
+        Self {
+            method: context.method.to_string(),
+            uri: context.uri.to_string(),
+            headers: context
+                .headers
+                .iter()
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                .collect(),
+        }
+    }
+}
+
+/// The JSON sent to hooks: the event `type`, the upload, its decoded metadata and the request.
+#[derive(Debug, Clone, Serialize)]
+pub struct HookPayload {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub upload: FileInfo<Created>,
+    pub metadata: BTreeMap<String, String>,
+    pub request: Option<HookRequest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HookPayload {
+    pub(crate) fn new(kind: &'static str, upload: FileInfo<Created>, context: Option<&RequestContext>) -> Self {
+        let metadata = upload
+            .metadata()
+            .iter()
+            .flat_map(|metadata| metadata.iter())
+            .map(|(key, value)| (key.to_string(), String::from_utf8_lossy(value).to_string()))
+            .collect();
+
+        Self {
+            kind,
+            upload,
+            metadata,
+            request: context.map(HookRequest::from),
+            error: None,
+        }
+    }
+}
+
+impl From<&UploadEvent> for HookPayload {
+    fn from(event: &UploadEvent) -> Self {
+        let mut payload = Self::new(event.kind.name(), event.file_info.clone(), event.context.as_ref());
+
+        if let crate::UploadEventKind::Failed(error) = &event.kind {
+            payload.error = Some(error.clone());
+        }
+
+        payload
+    }
+}
+
+/// Why a [`PreCreateHook`] rejected an upload, sent back to the client as the status and body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookRejection {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl HookRejection {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+}
+
+/// Decides whether an upload may be created, run after the metadata is validated and before the
+/// upload is saved, see [`TusConfig::with_pre_create_hook`](crate::TusConfig::with_pre_create_hook).
+#[async_trait]
+pub trait PreCreateHook: Send + Sync {
+    async fn pre_create(&self, payload: &HookPayload) -> Result<(), HookRejection>;
+}
+
+#[derive(Clone)]
+pub(crate) struct SharedPreCreateHook(pub(crate) Arc<dyn PreCreateHook>);
+
+impl fmt::Debug for SharedPreCreateHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PreCreateHook")
+    }
+}
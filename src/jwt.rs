@@ -0,0 +1,361 @@
+use futures::future::BoxFuture;
+use http::{header, HeaderValue, Request, Response, StatusCode};
+use axum::{body::Body, response::IntoResponse};
+use jsonwebtoken::{
+    jwk::{AlgorithmParameters, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::de::DeserializeOwned;
+use std::{collections::HashMap, error::Error, marker::PhantomData, path::Path, sync::Arc};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use crate::filesystem::file_info::unix_timestamp;
+use crate::tus_service::BoxBody;
+use crate::{AuthClaims, RequestClaims, TusHeaderMap, UploadSignature};
+
+pub type JwtError = Box<dyn Error + Send + Sync>;
+
+#[derive(Clone)]
+struct JwtKey {
+    key: DecodingKey,
+    algorithm: Algorithm,
+}
+
+#[derive(Clone)]
+enum JwtKeys {
+    Single(JwtKey),
+    // keyed by `kid`
+    Set(HashMap<String, JwtKey>),
+}
+
+impl JwtKeys {
+    fn find(&self, kid: Option<&str>) -> Option<&JwtKey> {
+        match (self, kid) {
+            (Self::Single(key), _) => Some(key),
+            (Self::Set(keys), Some(kid)) => keys.get(kid),
+            // a token without a `kid` can only be matched against a set of one key.
+            (Self::Set(keys), None) if keys.len() == 1 => keys.values().next(),
+            (Self::Set(_), None) => None,
+        }
+    }
+}
+
+/// A tower layer validating `Authorization: Bearer <jwt>` tokens signed with HS256 or RS256.
+///
+/// The payload is deserialized into `C`, rejected with `401 Unauthorized` once
+/// [`AuthClaims::get_expiration`] has passed, and inserted into the request extensions both as
+/// `C` and as a [`RequestClaims`], so the tus handlers record and check the owner of each upload.
+/// `OPTIONS` requests without a token are let through so clients can discover the server, and so
+/// are HEAD and PATCH requests carrying an upload signature, which the handlers check against
+/// the [`UploadSigner`](crate::UploadSigner) of [`TusConfig::with_signed_uploads`](crate::TusConfig::with_signed_uploads).
+///
+/// ```ignore
+/// let router = setup_tus_routes(Router::new(), store)
+///     .layer(JwtLayer::<ExampleJwtClaims>::hs256(b"secret"));
+/// ```
+pub struct JwtLayer<C> {
+    keys: Arc<JwtKeys>,
+    leeway: u64,
+    audience: Option<Vec<String>>,
+    issuer: Option<Vec<String>>,
+    claims: PhantomData<fn() -> C>,
+}
+
+impl<C> Clone for JwtLayer<C> {
+    fn clone(&self) -> Self {
+        Self {
+            keys: Arc::clone(&self.keys),
+            leeway: self.leeway,
+            audience: self.audience.clone(),
+            issuer: self.issuer.clone(),
+            claims: PhantomData,
+        }
+    }
+}
+
+impl<C> JwtLayer<C> {
+    fn with_keys(keys: JwtKeys) -> Self {
+        Self {
+            keys: Arc::new(keys),
+            leeway: 0,
+            audience: None,
+            issuer: None,
+            claims: PhantomData,
+        }
+    }
+
+    /// Validates HS256 tokens signed with `secret`.
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self::with_keys(JwtKeys::Single(JwtKey {
+            key: DecodingKey::from_secret(secret),
+            algorithm: Algorithm::HS256,
+        }))
+    }
+
+    /// Validates RS256 tokens signed by the private key of the PEM encoded `public_key`.
+    pub fn rs256_pem(public_key: &[u8]) -> Result<Self, JwtError> {
+        Ok(Self::with_keys(JwtKeys::Single(JwtKey {
+            key: DecodingKey::from_rsa_pem(public_key)?,
+            algorithm: Algorithm::RS256,
+        })))
+    }
+
+    /// Validates tokens against the HS256 and RS256 keys of a JWKS, matched on the token `kid`.
+    pub fn from_jwks(jwks: &JwkSet) -> Result<Self, JwtError> {
+        let mut keys = HashMap::new();
+
+        for jwk in &jwks.keys {
+            let algorithm = match (&jwk.algorithm, jwk.common.algorithm) {
+                (_, Some(algorithm @ (Algorithm::HS256 | Algorithm::RS256))) => algorithm,
+                (AlgorithmParameters::RSA(_), None) => Algorithm::RS256,
+                (AlgorithmParameters::OctetKey(_), None) => Algorithm::HS256,
+                _ => continue,
+            };
+
+            let kid = jwk.common.key_id.clone().unwrap_or_default();
+            keys.insert(kid, JwtKey {
+                key: DecodingKey::from_jwk(jwk)?,
+                algorithm,
+            });
+        }
+
+        if keys.is_empty() {
+            return Err("the JWKS has no HS256 or RS256 key".into());
+        }
+
+        Ok(Self::with_keys(JwtKeys::Set(keys)))
+    }
+
+    /// Reads a JWKS from a local JSON file, see [`JwtLayer::from_jwks`].
+    pub fn from_jwks_file(path: impl AsRef<Path>) -> Result<Self, JwtError> {
+        let jwks: JwkSet = serde_json::from_slice(&std::fs::read(path)?)?;
+
+        Self::from_jwks(&jwks)
+    }
+
+    /// Tolerates `leeway` seconds of clock skew when checking the expiration.
+    pub fn with_leeway(mut self, leeway: u64) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Only accepts tokens whose `aud` is one of `audience`.
+    pub fn with_audience(mut self, audience: &[&str]) -> Self {
+        self.audience = Some(audience.iter().map(|aud| aud.to_string()).collect());
+        self
+    }
+
+    /// Only accepts tokens whose `iss` is one of `issuer`.
+    pub fn with_issuer(mut self, issuer: &[&str]) -> Self {
+        self.issuer = Some(issuer.iter().map(|iss| iss.to_string()).collect());
+        self
+    }
+}
+
+impl<C> JwtLayer<C>
+where
+    C: AuthClaims + DeserializeOwned,
+{
+    fn validate(&self, token: &str) -> Option<C> {
+        let header = jsonwebtoken::decode_header(token).ok()?;
+        let key = self.keys.find(header.kid.as_deref())?;
+
+        if header.alg != key.algorithm {
+            return None;
+        }
+
+        let mut validation = Validation::new(key.algorithm);
+        // the expiration is checked below, through AuthClaims.
+        validation.validate_exp = false;
+        validation.required_spec_claims.clear();
+        validation.leeway = self.leeway;
+        if let Some(audience) = &self.audience {
+            validation.set_audience(audience);
+        }
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(issuer);
+        }
+
+        let claims = jsonwebtoken::decode::<C>(token, &key.key, &validation).ok()?.claims;
+
+        if claims.get_expiration() as u64 + self.leeway <= unix_timestamp() {
+            return None;
+        }
+
+        Some(claims)
+    }
+}
+
+impl<S, C> Layer<S> for JwtLayer<C> {
+    type Service = JwtService<S, C>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        JwtService {
+            service,
+            layer: self.clone(),
+        }
+    }
+}
+
+pub struct JwtService<S, C> {
+    service: S,
+    layer: JwtLayer<C>,
+}
+
+impl<S: Clone, C> Clone for JwtService<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+fn unauthorized() -> Response<BoxBody> {
+    let mut response = StatusCode::UNAUTHORIZED.into_response();
+    response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    TusHeaderMap::with_tus_version().apply(response.headers_mut());
+
+    response
+}
+
+impl<S, C> Service<Request<Body>> for JwtService<S, C>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Send + 'static,
+    S::Future: Send + 'static,
+    C: AuthClaims + DeserializeOwned + Clone + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let (mut parts, body) = request.into_parts();
+
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        let signed = matches!(parts.method, http::Method::HEAD | http::Method::PATCH)
+            && UploadSignature::is_present(&parts);
+
+        match token {
+            Some(token) => match self.layer.validate(token.trim()) {
+                Some(claims) => {
+                    parts.extensions.insert(RequestClaims(Arc::new(claims.clone())));
+                    parts.extensions.insert(claims);
+                },
+                None => return Box::pin(async { Ok(unauthorized()) }),
+            },
+            None if parts.method == http::Method::OPTIONS || signed => {},
+            None => return Box::pin(async { Ok(unauthorized()) }),
+        }
+
+        let fut = self.service.call(Request::from_parts(parts, body));
+
+        Box::pin(fut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header};
+    use tower::ServiceExt;
+
+    use crate::test_util::{create_upload, creation_request, memory_store, tus_app, tus_request};
+    use crate::{ExampleJwtClaims, FileStore, TusConfig, UploadSigner};
+
+    fn token(claims: &ExampleJwtClaims, secret: &[u8]) -> String {
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    async fn create(router: &axum::Router, token: Option<&str>) -> Response<BoxBody> {
+        let mut request = creation_request(4);
+
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+
+        router.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bearer_tokens_are_validated() {
+        let store = memory_store();
+        let router = tus_app(store.clone(), TusConfig::new())
+            .layer(JwtLayer::<ExampleJwtClaims>::hs256(b"secret"));
+
+        let mut claims = ExampleJwtClaims {
+            sub: "alice".to_string(),
+            user_id: "alice".to_string(),
+            exp: unix_timestamp() as usize + 60,
+        };
+
+        assert_eq!(create(&router, None).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(create(&router, Some(&token(&claims, b"wrong"))).await.status(), StatusCode::UNAUTHORIZED);
+
+        let response = create(&router, Some(&token(&claims, b"secret"))).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let id = response.headers()["Location"].to_str().unwrap().trim_start_matches('/').to_string();
+        assert_eq!(store.get_file_info(&id).await.unwrap().owner(), Some("alice"));
+
+        claims.exp = unix_timestamp() as usize - 60;
+        assert_eq!(create(&router, Some(&token(&claims, b"secret"))).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_signed_urls_bypass_bearer_tokens() {
+        let store = memory_store();
+        let id = create_upload(&store, 4).await.id().to_string();
+
+        let signer = UploadSigner::new(b"upload secret");
+        let router = tus_app(store, TusConfig::new().with_signed_uploads(signer.clone()))
+            .layer(JwtLayer::<ExampleJwtClaims>::hs256(b"secret"));
+
+        let head = |uri: String| {
+            let request = tus_request(http::Method::HEAD, &uri).body(Body::empty()).unwrap();
+            router.clone().oneshot(request)
+        };
+
+        let signed = signer.signed_url("", &id, unix_timestamp() + 60, None);
+        assert_eq!(head(signed).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(head(format!("/{}", id)).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        // a signature still has to be valid once past the layer
+        let forged = format!("/{}?expires={}&signature=forged", id, unix_timestamp() + 60);
+        assert_eq!(head(forged).await.unwrap().status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_jwks_keys_are_matched_on_kid() {
+        let jwks: JwkSet = serde_json::from_value(serde_json::json!({
+            "keys": [
+                { "kty": "oct", "kid": "first", "k": "Zmlyc3Q=" },
+                { "kty": "oct", "kid": "second", "k": "c2Vjb25k" }
+            ]
+        }))
+        .unwrap();
+        let layer = JwtLayer::<ExampleJwtClaims>::from_jwks(&jwks).unwrap();
+
+        let claims = ExampleJwtClaims {
+            sub: "bob".to_string(),
+            user_id: "bob".to_string(),
+            exp: unix_timestamp() as usize + 60,
+        };
+
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("second".to_string());
+        let signed = |secret: &[u8]| jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(secret)).unwrap();
+
+        assert_eq!(layer.validate(&signed(b"second")).unwrap().user_id, "bob");
+        assert!(layer.validate(&signed(b"first")).is_none());
+    }
+}
@@ -1,13 +1,64 @@
+mod config;
+mod cors;
+mod events;
 mod filesystem;
+mod hooks;
+mod location;
+mod metrics;
+mod process_hooks;
+mod resolver;
+#[cfg(feature = "jwt")]
+mod jwt;
+#[cfg(feature = "webhooks")]
+mod webhooks;
+mod signing;
+mod state;
 mod tus_service;
 mod request_handlers;
+#[cfg(test)]
+mod test_util;
 
-pub use filesystem::file_store::FileStore;
+pub use config::{DownloadConfig, TusConfig};
+pub use cors::CorsConfig;
+pub use events::{RequestContext, UploadEvent, UploadEventKind, UploadEvents};
+pub use hooks::{HookPayload, HookRejection, HookRequest, PreCreateHook, PRE_CREATE};
+pub use metrics::{
+    BYTES_RECEIVED_TOTAL, OFFSET_CONFLICTS_TOTAL, REQUESTS_TOTAL, REQUEST_DURATION_SECONDS, STORE_ERRORS_TOTAL,
+    UPLOADS_ACTIVE, UPLOADS_COMPLETED_TOTAL, UPLOADS_CREATED_TOTAL, UPLOADS_EXPIRED_TOTAL, UPLOADS_TERMINATED_TOTAL,
+};
+pub use process_hooks::ProcessHooks;
+pub use resolver::{StoreResolver, STORE_KEY_SEPARATOR};
+#[cfg(feature = "webhooks")]
+pub use webhooks::{WebhookError, WebhookNotifier, WEBHOOK_SIGNATURE_HEADER};
+pub use filesystem::file_store::{FileStore, LocalFileStore};
+#[cfg(feature = "jwt")]
+pub use jwt::{JwtError, JwtLayer, JwtService};
+pub use request_handlers::{AuthClaims, ExampleJwtClaims, RequestClaims};
+pub use signing::{UploadSignature, UploadSigner, UPLOAD_SIGNATURE_HEADER};
+pub use state::TusState;
+pub use filesystem::info_store::{InfoStore, JsonInfoStore, MemoryInfoStore};
+pub use filesystem::metadata::{Metadata, MetadataError};
+pub use filesystem::extensions::{FileInfoExtension, FileInfoExtensions};
+pub use filesystem::metadata_policy::{MetadataPolicy, MetadataViolation};
+pub use filesystem::object_file_store::{ObjectFileStore, ObjectInfoStore};
+pub use filesystem::quota::{
+    FileQuotaProvider, MemoryQuotaProvider, QuotaError, QuotaFileStore, QuotaLimits, QuotaProvider, QuotaSubject, QuotaUsage,
+};
+#[cfg(feature = "sqlite")]
+pub use filesystem::sqlite_info_store::SqliteInfoStore;
+#[cfg(feature = "sqlite")]
+pub use filesystem::registry::{RegistryFileStore, UploadQuery, UploadRecord, UploadRegistry, UploadState, UploadTransition};
 
 use request_handlers::creation::creation_handler;
+use request_handlers::download::download_handler;
+use request_handlers::termination::termination_handler;
 use request_handlers::file_info_handler::file_info_handler;
 use request_handlers::upload_handler::upload_handler;
 use request_handlers::info::info_handler;
+use request_handlers::progress::progress_handler;
+use request_handlers::admin::{
+    extend_expiry_handler, get_upload_handler, list_uploads_handler, stats_handler, terminate_upload_handler,
+};
 
 // TUS Headers for its protocol
 use http::header::HeaderMap;
@@ -15,20 +66,143 @@ use http::header::HeaderValue;
 use std::str::FromStr;
 use axum::routing::*;
 
-pub fn setup_tus_routes<T>(router: axum::Router, file_store: T) -> axum::Router
+pub fn setup_tus_routes<T, S>(router: axum::Router<S>, file_store: T) -> axum::Router<S>
 where
     T: FileStore + Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
 {
-    let tus_layer = tus_service::TusLayer::<T> {
-        file_store: std::sync::Arc::new(file_store)
+    setup_tus_routes_with_config(router, file_store, TusConfig::default())
+}
+
+/// Like [`setup_tus_routes`], with the optional behaviour configured by `config`,
+/// e.g. `TusConfig::new().with_download(DownloadConfig::new())` to also serve `GET /:id`.
+pub fn setup_tus_routes_with_config<T, S>(router: axum::Router<S>, file_store: T, config: TusConfig) -> axum::Router<S>
+where
+    T: FileStore + Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
+{
+    router.merge(TusRouterBuilder::new(file_store).with_config(config).build())
+}
+
+/// Builds the router of one tus endpoint, with its own store and [`TusConfig`] (and so its own
+/// limits, hooks, events and auth). Endpoints share nothing, so several can be merged side by side:
+///
+/// ```rust,ignore
+/// let avatars = TusRouterBuilder::new(LocalFileStore::new("/var/avatars".to_string()))
+///     .with_config(TusConfig::new().with_mount_path("/avatars").with_max_size(5_000_000))
+///     .build();
+/// let videos = TusRouterBuilder::new(ObjectFileStore::new(bucket))
+///     .with_config(TusConfig::new().with_mount_path("/videos").with_auth(video_claims))
+///     .build();
+///
+/// let app = Router::new().merge(avatars).merge(videos).with_state(app_state);
+/// ```
+pub struct TusRouterBuilder<T> {
+    state: TusState<T>,
+}
+
+impl<T> TusRouterBuilder<T>
+where
+    T: FileStore + Send + Sync + 'static,
+{
+    pub fn new(file_store: T) -> Self {
+        Self {
+            state: TusState::new(file_store, TusConfig::default()),
+        }
+    }
+
+    pub fn with_config(mut self, config: TusConfig) -> Self {
+        self.state.config = std::sync::Arc::new(config);
+        self
+    }
+
+    /// Picks the store of each upload with `resolver`, e.g. per tenant. The store passed to
+    /// [`new`](TusRouterBuilder::new) is used when it picks none.
+    pub fn with_store_resolver<R: StoreResolver<T> + 'static>(mut self, resolver: R) -> Self {
+        self.state = self.state.with_store_resolver(resolver);
+        self
+    }
+
+    /// The tus routes, under the [mount path](TusConfig::with_mount_path) of the config if set.
+    pub fn build<S>(self) -> axum::Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let mount_path = self.state.config.mount_path.clone();
+        let router = tus_router(self.state);
+
+        match mount_path {
+            Some(path) => axum::Router::new().nest(&path, router),
+            None => router,
+        }
+    }
+}
+
+/// The tus routes serving the store of `state`. They carry their own state, so the router
+/// can be merged or nested into a router with any state, e.g. `.nest("/files", tus_router(state))`.
+pub fn tus_router<T, S>(state: TusState<T>) -> axum::Router<S>
+where
+    T: FileStore + Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
+{
+    let mut upload_routes = head(file_info_handler::<T>)
+        .patch(upload_handler::<T>)
+        .delete(termination_handler::<T>);
+    if state.config.download.is_some() {
+        upload_routes = upload_routes.get(download_handler::<T>);
+    }
+
+    let mut routes = vec![
+        ("/", post(creation_handler::<T>).options(info_handler)),
+        ("/:id", upload_routes),
+    ];
+    if state.config.progress {
+        routes.push(("/:id/progress", get(progress_handler::<T>)));
+    }
+
+    let tus_layer = tus_service::TusLayer {
+        config: std::sync::Arc::clone(&state.config),
     };
 
-    let new_router = router
-        .route("/", post(creation_handler::<T>).options(info_handler))
-        .route("/:id", head(file_info_handler::<T>).patch(upload_handler::<T>))
-        .layer(tus_layer);
+    // the service wraps the method router of each path rather than each handler, so it sees
+    // requests before they are routed by method (to answer preflights and apply method overrides)
+    routes.into_iter().fold(axum::Router::new(), |router, (path, method_router)| {
+        let service = tower::Layer::layer(&tus_layer, method_router.with_state::<()>(state.clone()));
+        router.route_service(path, service)
+    })
+}
 
-    new_router
+/// Builds a router with admin endpoints to inspect and manage the uploads of every store of
+/// `state` (the default store and those of its [`StoreResolver`]), meant to be nested next to the
+/// tus routes, e.g. `.nest("/admin", admin_router(state, auth))`:
+///
+/// - `GET /uploads` - lists uploads, filtered by `state` (`in_progress` / `completed`), `owner`
+///   or `metadata_key`, and paginated with `offset` and `limit`.
+/// - `GET /uploads/:id` - returns the full info of an upload.
+/// - `DELETE /uploads/:id` - terminates an upload, emitting [`UploadEventKind::Terminated`] like
+///   the tus `DELETE`.
+/// - `POST /uploads/:id/expiry` - sets (`expires_at`) or extends (`extend_by`) the expiry.
+/// - `GET /stats` - returns aggregate stats over every upload.
+///
+/// Every route is guarded by `auth_layer` (e.g. a `tower_http` authorization layer, or an
+/// `axum::middleware::from_fn` checking your own credentials).
+pub fn admin_router<T, L, S>(state: TusState<T>, auth_layer: L) -> axum::Router<S>
+where
+    T: FileStore + Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
+    L: tower::Layer<Route> + Clone + Send + 'static,
+    L::Service: tower::Service<http::Request<axum::body::Body>> + Clone + Send + 'static,
+    <L::Service as tower::Service<http::Request<axum::body::Body>>>::Response: axum::response::IntoResponse + 'static,
+    <L::Service as tower::Service<http::Request<axum::body::Body>>>::Error: Into<std::convert::Infallible> + 'static,
+    <L::Service as tower::Service<http::Request<axum::body::Body>>>::Future: Send + 'static,
+{
+    axum::Router::new()
+        .route("/uploads", get(list_uploads_handler::<T>))
+        .route("/uploads/:id", get(get_upload_handler::<T>).delete(terminate_upload_handler::<T>))
+        .route("/uploads/:id/expiry", post(extend_expiry_handler::<T>))
+        .route("/stats", get(stats_handler::<T>))
+        .route_layer(auth_layer)
+        .with_state(state)
 }
 
 // TUS Headers for its protocol
@@ -40,7 +214,8 @@ pub enum AxumTusHeaders {
     Resumable,
     UploadLength,
     UploadOffset,
-    UploadMetadata
+    UploadMetadata,
+    MethodOverride,
 }
 
 pub enum TusExtensions {
@@ -55,6 +230,7 @@ impl TusExtensions {
     pub fn name(&self) -> String {
         match self {
             Self::Creation => "creation".to_string(),
+            Self::Termination => "termination".to_string(),
             _ => todo!("Not yet implemented"),
         }
     }
@@ -69,7 +245,8 @@ impl AxumTusHeaders {
             Self::Resumable => "Tus-Resumable",
             Self::UploadLength => "Upload-Length",
             Self::UploadOffset => "Upload-Offset",
-            Self::UploadMetadata => "Upload-Metadata"
+            Self::UploadMetadata => "Upload-Metadata",
+            Self::MethodOverride => "X-HTTP-Method-Override",
         }
     }
 }
@@ -91,12 +268,21 @@ impl TusHeaderMap {
         Self {
             resumable: Some("1.0.0".to_string()),
             version: Some(vec!["1.0.0".to_string()]),
-            extensions: Some(vec![TusExtensions::Creation.name()]), 
-            max_size: Some(300_000_000_000), //TODO Have configurable max size (300 gb default)
+            extensions: Some(vec![TusExtensions::Creation.name(), TusExtensions::Termination.name()]),
+            max_size: Some(config::DEFAULT_MAX_SIZE),
             ..Default::default()
         }
     }
 
+    /// The headers of [`with_tus_version`](TusHeaderMap::with_tus_version), advertising the
+    /// limits of `config`.
+    pub(crate) fn for_config(config: &TusConfig) -> Self {
+        Self {
+            max_size: Some(config.max_size()),
+            ..Self::with_tus_version()
+        }
+    }
+
     pub fn from_headers(headers: &HeaderMap) -> TusHeaderMap {
         let mut tus_header_map = TusHeaderMap::default();
     
@@ -165,7 +351,7 @@ impl TusHeaderMap {
         }
 
         if let Some(resumable) = &self.resumable {
-            headers.insert(AxumTusHeaders::Resumable.name(), HeaderValue::from_str(&resumable).unwrap());
+            headers.insert(AxumTusHeaders::Resumable.name(), HeaderValue::from_str(resumable).unwrap());
         }
 
         if let Some(upload_length) = &self.upload_length {
@@ -173,7 +359,7 @@ impl TusHeaderMap {
         }
 
         if let Some(upload_metadata) = &self.upload_metadata {
-            headers.insert(AxumTusHeaders::UploadMetadata.name(), HeaderValue::from_str(&upload_metadata).unwrap());
+            headers.insert(AxumTusHeaders::UploadMetadata.name(), HeaderValue::from_str(upload_metadata).unwrap());
         }
 
         if let Some(upload_offset) = &self.upload_offset {
@@ -182,6 +368,49 @@ impl TusHeaderMap {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::{Method, Request, StatusCode};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_util::{memory_store, tus_request};
+
+    fn creation(uri: &str, length: u64) -> Request<Body> {
+        tus_request(Method::POST, uri).header("Upload-Length", length).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_independent_endpoints() {
+        let avatars = memory_store();
+        let videos = memory_store();
+
+        let app: axum::Router = axum::Router::new()
+            .merge(
+                TusRouterBuilder::new(avatars.clone())
+                    .with_config(TusConfig::new().with_mount_path("/avatars").with_max_size(1_000))
+                    .build(),
+            )
+            .merge(
+                TusRouterBuilder::new(videos.clone())
+                    .with_config(TusConfig::new().with_mount_path("/videos"))
+                    .build(),
+            );
+
+        let response = app.clone().oneshot(creation("/avatars", 2_000)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response.headers()["Tus-Max-Size"], "1000");
+
+        let response = app.clone().oneshot(creation("/videos", 2_000)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()["Tus-Max-Size"], config::DEFAULT_MAX_SIZE.to_string().as_str());
+
+        assert!(avatars.list_files().await.unwrap().is_empty());
+        assert_eq!(videos.list_files().await.unwrap().len(), 1);
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use super::*;
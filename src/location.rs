@@ -0,0 +1,129 @@
+use http::{header, HeaderMap, Uri};
+use std::net::IpAddr;
+
+use crate::TusConfig;
+
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// The absolute URL of upload `id`, created by a POST to `uri` (the URI before any nesting).
+///
+/// The scheme and host come from the `Forwarded` or `X-Forwarded-Proto`/`X-Forwarded-Host`
+/// headers when `peer` is a trusted proxy, and from the `Host` header otherwise. Without a host
+/// the URL is relative to the server.
+pub(crate) fn upload_location(config: &TusConfig, headers: &HeaderMap, uri: &Uri, peer: Option<IpAddr>, id: &str) -> String {
+    let path = format!("{}/{}", uri.path().trim_end_matches('/'), id);
+
+    let (mut scheme, mut host) = (None, None);
+    if peer.is_some_and(|peer| config.trusted_proxies.contains(&peer)) {
+        (scheme, host) = match forwarded(headers) {
+            Some(forwarded) => forwarded,
+            None => (last_value(headers, X_FORWARDED_PROTO), last_value(headers, X_FORWARDED_HOST)),
+        };
+    }
+
+    let host = host
+        .or_else(|| headers.get(header::HOST)?.to_str().ok().map(str::to_string))
+        .or_else(|| uri.authority().map(|authority| authority.to_string()));
+    let scheme = scheme
+        .or_else(|| uri.scheme_str().map(str::to_string))
+        .unwrap_or_else(|| "http".to_string());
+
+    match host {
+        Some(host) => format!("{}://{}{}", scheme, host, path),
+        None => path,
+    }
+}
+
+/// The `proto` and `host` of the last element of the `Forwarded` header (RFC 7239), added by the
+/// trusted proxy. Earlier elements come from the client or untrusted proxies in front of it.
+fn forwarded(headers: &HeaderMap) -> Option<(Option<String>, Option<String>)> {
+    let element = last_line(headers, header::FORWARDED.as_str())?.rsplit(',').next()?;
+
+    let (mut proto, mut host) = (None, None);
+    for pair in element.split(';') {
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').to_string();
+
+        match key.trim().to_ascii_lowercase().as_str() {
+            "proto" => proto = Some(value),
+            "host" => host = Some(value),
+            _ => {},
+        }
+    }
+
+    Some((proto, host))
+}
+
+/// The last of the comma separated values of `name`, set by the trusted proxy.
+fn last_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    let value = last_line(headers, name)?.rsplit(',').next()?.trim();
+
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// The last line of header `name`: proxies may append their own line rather than joining the
+/// value of the client's.
+fn last_line<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get_all(name).iter().next_back()?.to_str().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (header::HeaderName::from_static(name), value.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_upload_location() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let config = TusConfig::new().with_trusted_proxies([proxy]);
+        let uri = Uri::from_static("/api/files/");
+
+        let direct = headers(&[("host", "tus.example.com")]);
+        assert_eq!(upload_location(&config, &direct, &uri, None, "1"), "http://tus.example.com/api/files/1");
+        assert_eq!(upload_location(&config, &HeaderMap::new(), &uri, None, "1"), "/api/files/1");
+
+        let proxied = headers(&[
+            ("host", "tus-0.internal:8080"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "spoofed.example.com, uploads.example.com"),
+        ]);
+        assert_eq!(upload_location(&config, &proxied, &uri, Some(proxy), "1"), "https://uploads.example.com/api/files/1");
+
+        // forwarded headers from anyone else are ignored
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        assert_eq!(upload_location(&config, &proxied, &uri, Some(client), "1"), "http://tus-0.internal:8080/api/files/1");
+
+        let forwarded = headers(&[
+            ("host", "tus-0.internal:8080"),
+            ("forwarded", "for=198.51.100.1;host=spoofed.example.com, for=203.0.113.7;proto=https;host=\"uploads.example.com\""),
+            ("x-forwarded-host", "ignored.example.com"),
+        ]);
+        assert_eq!(upload_location(&config, &forwarded, &uri, Some(proxy), "1"), "https://uploads.example.com/api/files/1");
+    }
+
+    #[test]
+    fn test_upload_location_uses_the_last_header_line() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let config = TusConfig::new().with_trusted_proxies([proxy]);
+        let uri = Uri::from_static("/api/files/");
+
+        let mut proxied = headers(&[("host", "tus-0.internal:8080")]);
+        proxied.append(X_FORWARDED_HOST, "spoofed.example.com".parse().unwrap());
+        proxied.append(X_FORWARDED_HOST, "uploads.example.com".parse().unwrap());
+        assert_eq!(upload_location(&config, &proxied, &uri, Some(proxy), "1"), "http://uploads.example.com/api/files/1");
+
+        let mut forwarded = headers(&[("host", "tus-0.internal:8080")]);
+        forwarded.append(header::FORWARDED, "proto=http;host=spoofed.example.com".parse().unwrap());
+        forwarded.append(header::FORWARDED, "proto=https;host=uploads.example.com".parse().unwrap());
+        assert_eq!(upload_location(&config, &forwarded, &uri, Some(proxy), "1"), "https://uploads.example.com/api/files/1");
+    }
+}
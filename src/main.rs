@@ -2,13 +2,14 @@ use axum::Router;
 use axum_tus::setup_tus_routes;
 use axum_tus::LocalFileStore;
 
-fn main() {
+#[tokio::main]
+async fn main() {
     /* need to localize the root path better. */
-    let store = LocalFileStore::new("/tmp/tus-store").unwrap();
+    let store = LocalFileStore::new("/tmp/tus-store".to_string());
 
     let mut app = Router::new();
     
-    app = setup_tus_routes::<LocalFileStore>(app, store);
+    app = setup_tus_routes(app, store);
 
     let addr = "127.0.0.1:8001".parse().unwrap();
     axum::Server::bind(&addr)
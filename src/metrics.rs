@@ -0,0 +1,190 @@
+// Recorded through the `metrics` facade with the `metrics` feature, so any exporter (e.g.
+// Prometheus) can collect them. Without the feature every function here does nothing.
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::{collections::BTreeSet, sync::Mutex};
+
+use crate::filesystem::{
+    file_info::FileInfo,
+    file_store::FileStoreError,
+};
+
+/// Every tus request, labelled by `method` and `status`.
+pub const REQUESTS_TOTAL: &str = "tus_requests_total";
+/// Request latency in seconds, labelled by `method`.
+pub const REQUEST_DURATION_SECONDS: &str = "tus_request_duration_seconds";
+pub const UPLOADS_CREATED_TOTAL: &str = "tus_uploads_created_total";
+pub const UPLOADS_COMPLETED_TOTAL: &str = "tus_uploads_completed_total";
+pub const UPLOADS_TERMINATED_TOTAL: &str = "tus_uploads_terminated_total";
+pub const UPLOADS_EXPIRED_TOTAL: &str = "tus_uploads_expired_total";
+/// Uploads created by this process and not completed, terminated or expired yet. Uploads
+/// created before it started are left out, so the gauge starts at zero on every restart.
+pub const UPLOADS_ACTIVE: &str = "tus_uploads_active";
+pub const BYTES_RECEIVED_TOTAL: &str = "tus_bytes_received_total";
+/// PATCH requests whose `Upload-Offset` didn't match the upload.
+pub const OFFSET_CONFLICTS_TOTAL: &str = "tus_offset_conflicts_total";
+/// Failed store operations, labelled by `operation` and `error`.
+pub const STORE_ERRORS_TOTAL: &str = "tus_store_errors_total";
+
+/// Ids of the uploads counted in [`UPLOADS_ACTIVE`], so uploads this process never counted
+/// aren't subtracted from it.
+#[cfg(feature = "metrics")]
+static ACTIVE_UPLOADS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+#[cfg(feature = "metrics")]
+fn stop_counting_active<State>(file_info: &FileInfo<State>) {
+    if ACTIVE_UPLOADS.lock().unwrap().remove(file_info.id()) {
+        metrics::gauge!(UPLOADS_ACTIVE).decrement(1.0);
+    }
+}
+
+pub(crate) fn request(method: &http::Method, status: http::StatusCode, duration: Duration) {
+    #[cfg(feature = "metrics")]
+    {
+        let method = method.to_string();
+        metrics::counter!(REQUESTS_TOTAL, "method" => method.clone(), "status" => status.as_u16().to_string()).increment(1);
+        metrics::histogram!(REQUEST_DURATION_SECONDS, "method" => method).record(duration.as_secs_f64());
+    }
+}
+
+pub(crate) fn upload_created<State>(file_info: &FileInfo<State>) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!(UPLOADS_CREATED_TOTAL).increment(1);
+        if ACTIVE_UPLOADS.lock().unwrap().insert(file_info.id().to_string()) {
+            metrics::gauge!(UPLOADS_ACTIVE).increment(1.0);
+        }
+    }
+}
+
+pub(crate) fn upload_completed<State>(file_info: &FileInfo<State>) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!(UPLOADS_COMPLETED_TOTAL).increment(1);
+        stop_counting_active(file_info);
+    }
+}
+
+pub(crate) fn upload_terminated<State>(file_info: &FileInfo<State>) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!(UPLOADS_TERMINATED_TOTAL).increment(1);
+        stop_counting_active(file_info);
+    }
+}
+
+pub(crate) fn upload_expired<State>(file_info: &FileInfo<State>) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!(UPLOADS_EXPIRED_TOTAL).increment(1);
+        stop_counting_active(file_info);
+    }
+}
+
+pub(crate) fn bytes_received(bytes: u64) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(BYTES_RECEIVED_TOTAL).increment(bytes);
+}
+
+pub(crate) fn offset_conflict() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(OFFSET_CONFLICTS_TOTAL).increment(1);
+}
+
+pub(crate) fn store_error(operation: &'static str, error: &FileStoreError) {
+    #[cfg(feature = "metrics")]
+    {
+        let error = match error {
+            FileStoreError::CreationError(_) => "creation",
+            FileStoreError::ReadError(_) => "read",
+            FileStoreError::TerminationError(_) => "termination",
+            FileStoreError::QuotaExceeded => "quota_exceeded",
+            FileStoreError::Error => "other",
+        };
+        metrics::counter!(STORE_ERRORS_TOTAL, "operation" => operation, "error" => error).increment(1);
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use axum::body::Body;
+    use http::{Method, StatusCode};
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_util::{create_upload, creation_request, memory_store, tus_app, tus_request};
+    use crate::{FileStore, TusConfig};
+
+    fn counter(snapshotter: &Snapshotter, name: &str) -> u64 {
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .filter(|(key, ..)| key.key().name() == name)
+            .map(|(.., value)| match value {
+                DebugValue::Counter(value) => value,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn gauge(snapshotter: &Snapshotter, name: &str) -> f64 {
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .filter(|(key, ..)| key.key().name() == name)
+            .map(|(.., value)| match value {
+                DebugValue::Gauge(value) => value.into_inner(),
+                _ => 0.0,
+            })
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn test_metrics_are_recorded() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let store = memory_store();
+        let app = tus_app(store.clone(), TusConfig::new());
+
+        let request = creation_request(3).body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CREATED);
+
+        let id = store.list_files().await.unwrap().remove(0).id().to_string();
+        let request = tus_request(Method::DELETE, &format!("/{}", id)).body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+
+        let request = tus_request(Method::DELETE, &format!("/{}", id)).body(Body::empty()).unwrap();
+        assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        assert_eq!(counter(&snapshotter, UPLOADS_CREATED_TOTAL), 1);
+        assert_eq!(counter(&snapshotter, UPLOADS_TERMINATED_TOTAL), 1);
+        assert_eq!(counter(&snapshotter, REQUESTS_TOTAL), 3);
+    }
+
+    #[tokio::test]
+    async fn test_active_uploads_only_count_uploads_of_this_process() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        // created before the process started
+        let store = memory_store();
+        let previous = create_upload(&store, 3).await;
+        let app = tus_app(store, TusConfig::new());
+
+        let response = app.clone().oneshot(creation_request(3).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(gauge(&snapshotter, UPLOADS_ACTIVE), 1.0);
+
+        let request = tus_request(Method::DELETE, &format!("/{}", previous.id())).body(Body::empty()).unwrap();
+        assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(gauge(&snapshotter, UPLOADS_ACTIVE), 1.0);
+    }
+}
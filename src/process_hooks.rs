@@ -0,0 +1,320 @@
+use async_trait::async_trait;
+use http::StatusCode;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    process::Command,
+    sync::{broadcast::error::RecvError, Semaphore},
+    task::JoinHandle,
+};
+
+use crate::{HookPayload, HookRejection, PreCreateHook, UploadEventKind, UploadEvents};
+
+/// Post hooks running at once, the events after them wait for a slot.
+const MAX_RUNNING_POST_HOOKS: usize = 16;
+
+/// Runs executables on upload events, like the file hooks of tusd. Each one receives the
+/// [`HookPayload`] as JSON on stdin.
+///
+/// Used as a [`PreCreateHook`], a non-zero exit of the pre-create executable rejects the upload
+/// with `400 Bad Request` and its stdout as the body. Post hooks are run by
+/// [`listen`](ProcessHooks::listen), and their exit status is only logged.
+///
+/// ```rust,ignore
+/// let events = UploadEvents::new(1024);
+/// let hooks = ProcessHooks::new()
+///     .with_pre_create("/etc/tus/hooks/pre-create")
+///     .with_post_finish("/etc/tus/hooks/post-finish");
+/// hooks.listen(&events);
+///
+/// let config = TusConfig::new().with_events(events).with_pre_create_hook(hooks);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProcessHooks {
+    pre_create: Option<PathBuf>,
+    post_finish: Option<PathBuf>,
+    post_terminate: Option<PathBuf>,
+    timeout: Duration,
+}
+
+impl Default for ProcessHooks {
+    fn default() -> Self {
+        Self {
+            pre_create: None,
+            post_finish: None,
+            post_terminate: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ProcessHooks {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Run before an upload is created, deciding whether it may be.
+    pub fn with_pre_create(mut self, program: impl Into<PathBuf>) -> Self {
+        self.pre_create = Some(program.into());
+        self
+    }
+
+    /// Run when an upload is completed.
+    pub fn with_post_finish(mut self, program: impl Into<PathBuf>) -> Self {
+        self.post_finish = Some(program.into());
+        self
+    }
+
+    /// Run when an upload is terminated or removed once expired.
+    pub fn with_post_terminate(mut self, program: impl Into<PathBuf>) -> Self {
+        self.post_terminate = Some(program.into());
+        self
+    }
+
+    /// Kills executables running longer than `timeout` (30 seconds by default).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Runs the post hooks for the upload events of `events` in the background until they are
+    /// dropped. Each hook runs in its own task, at most 16 at a time, so a slow one doesn't hold
+    /// up the events after it.
+    pub fn listen(&self, events: &UploadEvents) -> JoinHandle<()> {
+        let hooks = Arc::new(self.clone());
+        let running = Arc::new(Semaphore::new(MAX_RUNNING_POST_HOOKS));
+        let mut receiver = events.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!(missed, "process hooks missed upload events");
+                        continue;
+                    },
+                    Err(RecvError::Closed) => break,
+                };
+
+                let program = match event.kind {
+                    UploadEventKind::Completed => &hooks.post_finish,
+                    UploadEventKind::Terminated | UploadEventKind::Expired => &hooks.post_terminate,
+                    _ => continue,
+                };
+                let Some(program) = program.clone() else {
+                    continue;
+                };
+
+                let (hooks, running) = (Arc::clone(&hooks), Arc::clone(&running));
+                tokio::spawn(async move {
+                    let Ok(_permit) = running.acquire().await else {
+                        return;
+                    };
+
+                    match hooks.run(&program, &HookPayload::from(&event)).await {
+                        Ok(output) if output.status.success() => {},
+                        Ok(output) => tracing::error!(
+                            hook = %program.display(),
+                            upload_id = event.file_info.id(),
+                            status = %output.status,
+                            "hook failed"
+                        ),
+                        Err(e) => tracing::error!(hook = %program.display(), error = %e, "could not run hook"),
+                    }
+                });
+            }
+        })
+    }
+
+    /// Runs `program` with `payload` on stdin, killing it once it has run for the timeout.
+    async fn run(&self, program: &Path, payload: &HookPayload) -> io::Result<Output> {
+        let input = serde_json::to_vec(payload)?;
+
+        let mut child = Command::new(program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        // stdin is written while stdout is read, so neither blocks the executable
+        let stdin = child.stdin.take();
+        let write = async move {
+            match stdin {
+                Some(mut stdin) => stdin.write_all(&input).await,
+                None => Ok(()),
+            }
+        };
+
+        let (written, output) = match tokio::time::timeout(self.timeout, async { tokio::join!(write, child.wait_with_output()) }).await {
+            Ok(finished) => finished,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, "the hook timed out")),
+        };
+
+        // the executable may exit without reading its input
+        if let Err(e) = written {
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                return Err(e);
+            }
+        }
+
+        output
+    }
+}
+
+#[async_trait]
+impl PreCreateHook for ProcessHooks {
+    async fn pre_create(&self, payload: &HookPayload) -> Result<(), HookRejection> {
+        let Some(program) = &self.pre_create else {
+            return Ok(());
+        };
+
+        match self.run(program, payload).await {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => Err(HookRejection::new(
+                StatusCode::BAD_REQUEST,
+                String::from_utf8_lossy(&output.stdout).trim(),
+            )),
+            Err(e) => {
+                tracing::error!(hook = %program.display(), error = %e, "could not run hook");
+                Err(HookRejection::new(StatusCode::INTERNAL_SERVER_ERROR, ""))
+            },
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use axum::body::Body;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use http::Request;
+    use std::os::unix::fs::PermissionsExt;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_util::{create_upload, creation_request, eventually, memory_store, tus_app};
+    use crate::{FileStore, TusConfig};
+
+    /// A temporary directory for the scripts of a test, removed when dropped.
+    struct ScriptDir(PathBuf);
+
+    impl ScriptDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("axum_tus_hooks_{}", uuid::Uuid::new_v4().simple()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write_script(&self, name: &str, script: &str) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::write(&path, script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        }
+    }
+
+    impl Drop for ScriptDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn creation(file_name: &str) -> Request<Body> {
+        creation_request(3)
+            .header("Upload-Metadata", format!("filename {}", STANDARD.encode(file_name)))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pre_create_process_rejects_uploads() {
+        let dir = ScriptDir::new();
+        let pre_create = dir.write_script(
+            "pre-create",
+            "#!/bin/sh\ncase \"$(cat)\" in *'\"filename\":\"secret.txt\"'*) echo 'secret files are not allowed'; exit 1;; esac\n",
+        );
+
+        let hooks = ProcessHooks::new().with_pre_create(pre_create);
+        let app = tus_app(memory_store(), TusConfig::new().with_pre_create_hook(hooks));
+
+        let response = app.clone().oneshot(creation("secret.txt")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, "secret files are not allowed");
+
+        let response = app.oneshot(creation("report.txt")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_process_not_reading_its_input_is_killed_after_the_timeout() {
+        let dir = ScriptDir::new();
+        let pre_create = dir.write_script("pre-create", "#!/bin/sh\nexec sleep 10\n");
+        let hooks = ProcessHooks::new().with_pre_create(pre_create).with_timeout(Duration::from_millis(200));
+
+        // larger than the pipe buffer, so writing it blocks until the process reads it
+        let file_info = create_upload(&memory_store(), 3).await;
+        let mut payload = HookPayload::new("pre-create", file_info, None);
+        payload.metadata.insert("padding".to_string(), "x".repeat(1024 * 1024));
+
+        let started = std::time::Instant::now();
+        let rejection = hooks.pre_create(&payload).await.unwrap_err();
+
+        assert_eq!(rejection.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_post_finish_process_receives_the_upload() {
+        let dir = ScriptDir::new();
+        let finished = dir.0.join("finished.json");
+        let post_finish = dir.write_script("post-finish", &format!("#!/bin/sh\ncat > {}\n", finished.display()));
+
+        let events = UploadEvents::new(16);
+        ProcessHooks::new().with_post_finish(post_finish).listen(&events);
+
+        let store = memory_store();
+        let metadata = format!("filename {}", STANDARD.encode("report.txt"));
+        let file_info = store.create_file(store.build_file(3, Some(&metadata)).await.unwrap()).await.unwrap();
+        let id = file_info.id().to_string();
+        events.emit(UploadEventKind::Completed, file_info, None);
+
+        eventually(|| std::fs::read(&finished).is_ok_and(|payload| !payload.is_empty())).await;
+
+        let payload: serde_json::Value = serde_json::from_slice(&std::fs::read(&finished).unwrap()).unwrap();
+        assert_eq!(payload["type"], "completed");
+        assert_eq!(payload["upload"]["id"], id.as_str());
+        assert_eq!(payload["metadata"]["filename"], "report.txt");
+    }
+
+    #[tokio::test]
+    async fn test_slow_post_finish_process_does_not_hold_up_later_uploads() {
+        let dir = ScriptDir::new();
+        let finished = dir.0.join("finished.json");
+        let post_finish = dir.write_script(
+            "post-finish",
+            &format!("#!/bin/sh\npayload=$(cat)\ncase \"$payload\" in *slow.txt*) sleep 10;; esac\necho \"$payload\" > {}\n", finished.display()),
+        );
+
+        let events = UploadEvents::new(16);
+        ProcessHooks::new().with_post_finish(post_finish).listen(&events);
+
+        let store = memory_store();
+        for file_name in ["slow.txt", "fast.txt"] {
+            let metadata = format!("filename {}", STANDARD.encode(file_name));
+            let file_info = store.create_file(store.build_file(3, Some(&metadata)).await.unwrap()).await.unwrap();
+            events.emit(UploadEventKind::Completed, file_info, None);
+        }
+
+        eventually(|| std::fs::read(&finished).is_ok_and(|payload| !payload.is_empty())).await;
+
+        let payload: serde_json::Value = serde_json::from_slice(&std::fs::read(&finished).unwrap()).unwrap();
+        assert_eq!(payload["metadata"]["filename"], "fast.txt");
+    }
+}
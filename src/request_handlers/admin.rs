@@ -0,0 +1,350 @@
+use axum::{
+    extract::{Extension, OriginalUri, Path, Query, State},
+    http::{HeaderMap, Method, StatusCode},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use crate::{RequestClaims, RequestContext, TusState};
+use crate::filesystem::file_info::{unix_timestamp, Created, FileInfo};
+use crate::filesystem::file_store::*;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadProgress {
+    InProgress,
+    Completed,
+}
+
+/// An upload as returned by the admin endpoints: the full [`FileInfo`] and whether it completed.
+#[derive(Debug, Serialize)]
+pub struct AdminUpload {
+    #[serde(flatten)]
+    file_info: FileInfo<Created>,
+    state: UploadProgress,
+}
+
+impl From<FileInfo<Created>> for AdminUpload {
+    fn from(file_info: FileInfo<Created>) -> Self {
+        let state = if file_info.offset() == file_info.length() {
+            UploadProgress::Completed
+        } else {
+            UploadProgress::InProgress
+        };
+
+        Self {
+            file_info,
+            state,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListParams {
+    state: Option<UploadProgress>,
+    owner: Option<String>,
+    metadata_key: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+impl ListParams {
+    fn matches(&self, upload: &AdminUpload) -> bool {
+        if self.state.is_some_and(|state| state != upload.state) {
+            return false;
+        }
+
+        if let Some(owner) = &self.owner {
+            if upload.file_info.owner() != Some(owner.as_str()) {
+                return false;
+            }
+        }
+
+        if let Some(key) = &self.metadata_key {
+            let has_key = match upload.file_info.metadata() {
+                Some(metadata) => metadata.keys().any(|k| k == key),
+                None => false,
+            };
+
+            if !has_key {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadList {
+    uploads: Vec<AdminUpload>,
+    total: usize,
+    offset: usize,
+    limit: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct UploadStats {
+    uploads: usize,
+    in_progress: usize,
+    completed: usize,
+    expired: usize,
+    total_bytes: u64,
+    received_bytes: u64,
+}
+
+/// Either sets the expiry to `expires_at` (seconds since the unix epoch), or pushes the current
+/// expiry (or now, if the upload never expires or already expired) back by `extend_by` seconds.
+#[derive(Debug, Deserialize)]
+pub struct ExpiryRequest {
+    expires_at: Option<u64>,
+    extend_by: Option<u64>,
+}
+
+/// The uploads of every store of `state`.
+async fn list_files<T: FileStore>(state: &TusState<T>) -> Result<Vec<FileInfo<Created>>, StatusCode> {
+    let mut files = Vec::new();
+
+    for file_store in state.stores() {
+        match file_store.list_files().await {
+            Ok(store_files) => files.extend(store_files),
+            Err(e) => {
+                tracing::error!(error = %e, "could not list uploads");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+pub async fn list_uploads_handler<T>(
+    State(state): State<TusState<T>>,
+    Query(params): Query<ListParams>,
+) -> Result<Json<UploadList>, StatusCode>
+where
+    T: FileStore + Send + Sync + 'static
+{
+    let mut files = list_files(&state).await?;
+    files.sort_by(|a, b| a.id().cmp(b.id()));
+
+    let uploads: Vec<AdminUpload> = files
+        .into_iter()
+        .map(AdminUpload::from)
+        .filter(|upload| params.matches(upload))
+        .collect();
+
+    let offset = params.offset.unwrap_or_default();
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+
+    Ok(Json(UploadList {
+        total: uploads.len(),
+        uploads: uploads.into_iter().skip(offset).take(limit).collect(),
+        offset,
+        limit,
+    }))
+}
+
+pub async fn get_upload_handler<T>(
+    State(state): State<TusState<T>>,
+    Path(id): Path<String>,
+) -> Result<Json<AdminUpload>, StatusCode>
+where
+    T: FileStore + Send + Sync + 'static
+{
+    let file_store = state.store_of(&id).ok_or(StatusCode::NOT_FOUND)?;
+
+    match file_store.get_file_info(&id).await {
+        Ok(file_info) => Ok(Json(AdminUpload::from(file_info))),
+        Err(_) => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+pub async fn terminate_upload_handler<T>(
+    State(state): State<TusState<T>>,
+    Path(id): Path<String>,
+    method: Method,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    claims: Option<Extension<RequestClaims>>,
+) -> StatusCode
+where
+    T: FileStore + Send + Sync + 'static
+{
+    let Some(file_store) = state.store_of(&id) else {
+        return StatusCode::NOT_FOUND;
+    };
+    let Ok(file_info) = file_store.get_file_info(&id).await else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let context = RequestContext::new(method, uri, headers, claims.as_deref());
+    super::termination::terminate(&state, &file_store, file_info, Some(&context)).await
+}
+
+pub async fn extend_expiry_handler<T>(
+    State(state): State<TusState<T>>,
+    Path(id): Path<String>,
+    Json(request): Json<ExpiryRequest>,
+) -> Result<Json<AdminUpload>, StatusCode>
+where
+    T: FileStore + Send + Sync + 'static
+{
+    let file_store = state.store_of(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let file_info = match file_store.get_file_info(&id).await {
+        Ok(file_info) => file_info,
+        Err(_) => return Err(StatusCode::NOT_FOUND),
+    };
+
+    let expires_at = match (request.expires_at, request.extend_by) {
+        (Some(expires_at), None) => expires_at,
+        (None, Some(extend_by)) => {
+            let now = unix_timestamp();
+            file_info.expires_at().unwrap_or(now).max(now) + extend_by
+        },
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    match file_store.set_expiry(&id, Some(expires_at)).await {
+        Ok(file_info) => Ok(Json(AdminUpload::from(file_info))),
+        Err(e) => {
+            tracing::error!(upload_id = id.as_str(), error = %e, "could not extend the expiry of upload");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub async fn stats_handler<T>(
+    State(state): State<TusState<T>>,
+) -> Result<Json<UploadStats>, StatusCode>
+where
+    T: FileStore + Send + Sync + 'static
+{
+    let files = list_files(&state).await?;
+
+    let now = unix_timestamp();
+    let mut stats = UploadStats::default();
+
+    for file_info in files {
+        stats.uploads += 1;
+        stats.total_bytes += file_info.length();
+        stats.received_bytes += file_info.offset();
+
+        if file_info.offset() == file_info.length() {
+            stats.completed += 1;
+        } else {
+            stats.in_progress += 1;
+        }
+
+        if file_info.expires_at().is_some_and(|expires_at| expires_at <= now) {
+            stats.expired += 1;
+        }
+    }
+
+    Ok(Json(stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        middleware::{self, Next},
+        response::Response,
+    };
+    use http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    use crate::test_util::{create_upload, memory_store};
+    use crate::{admin_router, FileStore, TusConfig, TusState, UploadEventKind, UploadEvents};
+
+    async fn require_token<B>(request: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
+        match request.headers().get(http::header::AUTHORIZATION) {
+            Some(token) if token == "Bearer admin" => Ok(next.run(request).await),
+            _ => Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+
+    async fn send(router: &axum::Router, method: &str, uri: &str, body: Body) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(http::header::AUTHORIZATION, "Bearer admin")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap();
+
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes() {
+        let store = memory_store();
+
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            ids.push(create_upload(&store, 4).await.id().to_string());
+        }
+        store.patch_file(&ids[0], 0, &mut b"abcd".to_vec()).await.unwrap();
+
+        let router = admin_router(TusState::new(store, TusConfig::new()), middleware::from_fn(require_token));
+
+        let unauthorized = router
+            .clone()
+            .oneshot(Request::builder().uri("/uploads").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+
+        let (status, list) = send(&router, "GET", "/uploads?state=in_progress&limit=1", Body::empty()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(list["total"], 2);
+        assert_eq!(list["uploads"].as_array().unwrap().len(), 1);
+
+        let (_, upload) = send(&router, "GET", &format!("/uploads/{}", ids[0]), Body::empty()).await;
+        assert_eq!(upload["state"], "completed");
+        assert_eq!(upload["offset"], 4);
+
+        let (status, upload) = send(
+            &router,
+            "POST",
+            &format!("/uploads/{}/expiry", ids[1]),
+            Body::from(r#"{"expires_at": 4102444800}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(upload["expires_at"], 4102444800u64);
+
+        let (status, _) = send(&router, "DELETE", &format!("/uploads/{}", ids[2]), Body::empty()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (_, stats) = send(&router, "GET", "/stats", Body::empty()).await;
+        assert_eq!(stats["uploads"], 2);
+        assert_eq!(stats["completed"], 1);
+        assert_eq!(stats["received_bytes"], 4);
+    }
+
+    #[tokio::test]
+    async fn test_admin_terminations_are_broadcast() {
+        let store = memory_store();
+        let id = create_upload(&store, 4).await.id().to_string();
+
+        let events = UploadEvents::new(16);
+        let mut receiver = events.subscribe();
+        let state = TusState::new(store, TusConfig::new().with_events(events));
+        let router = admin_router(state, middleware::from_fn(require_token));
+
+        let (status, _) = send(&router, "DELETE", &format!("/uploads/{}", id), Body::empty()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.kind, UploadEventKind::Terminated);
+        assert_eq!(event.file_info.id(), id);
+        assert_eq!(event.context.unwrap().method, http::Method::DELETE);
+    }
+}
@@ -1,51 +1,120 @@
 use async_trait::async_trait;
 use axum::{
-    extract::{Extension, Path, FromRequest, FromRequestParts},
+    extract::{ConnectInfo, Extension, OriginalUri, FromRequest, State},
     http::{Response, StatusCode}, response::IntoResponse,
 };
-use hyper::{ Request, Uri, HeaderMap};
+use hyper::{ Request, HeaderMap};
 use axum::body::Body;
-use std::{io::Cursor, sync::Arc, convert::Infallible};
-use crate::TusHeaderMap;
+use std::{convert::Infallible, net::SocketAddr};
+use crate::{HookPayload, RequestContext, TusHeaderMap, TusState, UploadEventKind, PRE_CREATE};
 use crate::filesystem::file_store::*;
+use crate::filesystem::metadata::MetadataError;
 
-use super::AuthClaims;
+use super::RequestClaims;
 
-pub struct CreationRequest<T> {
+pub struct CreationRequest {
     upload_length: u64,
     metadata: Option<String>,
-    file_store: Arc<T>
 }
 
 pub async fn creation_handler<T>(
-    req: CreationRequest<T>,
-    // claims: A,
+    State(state): State<TusState<T>>,
+    claims: Option<Extension<RequestClaims>>,
+    context: Option<Extension<RequestContext>>,
+    OriginalUri(uri): OriginalUri,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    req: CreationRequest,
 ) -> Result<impl IntoResponse, Infallible> 
 where 
     T: FileStore + Send + Sync + 'static
 {
-    // NOTE - To allow extendability, we're fetching the file store from the request
-    let file_store = req.file_store;
-    
+    let config = &state.config;
+
+    if req.upload_length > config.max_size() {
+        return Ok(Response::builder().status(StatusCode::PAYLOAD_TOO_LARGE).body(Body::empty()).unwrap());
+    }
+
+    if let Some(policy) = &config.metadata_policy {
+        if let Err(violation) = policy.validate(req.metadata.as_deref()) {
+            return Ok(Response::builder().status(StatusCode::BAD_REQUEST).body(Body::from(violation.to_string())).unwrap());
+        }
+    }
+
+    let claims = claims.map(|Extension(RequestClaims(claims))| claims);
+    let (file_store, store_key) = match state.resolve(context.as_deref(), claims.as_deref()) {
+        Ok(resolved) => resolved,
+        Err(status) => return Ok(Response::builder().status(status).body(Body::empty()).unwrap()),
+    };
+
     let file_info = match file_store.build_file(req.upload_length, req.metadata.as_deref()).await {
         Ok(info) => info,
+        Err(FileStoreError::CreationError(e)) if e.is::<MetadataError>() => {
+            return Ok(Response::builder().status(StatusCode::BAD_REQUEST).body(Body::from(e.to_string())).unwrap());
+        },
         Err(e) => {
-            println!("Error building file: {:?}", e);
+            tracing::error!(error = %e, "could not build upload");
+            crate::metrics::store_error("build", &e);
             return Ok(Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap());
         }
     };
 
+    let file_info = match claims {
+        Some(claims) => file_info
+            .with_owner(claims.get_user_id())
+            .with_tenant(claims.get_tenant_id()),
+        None => file_info,
+    };
+
+    let file_info = match &store_key {
+        Some(key) => file_info.with_store_key(key),
+        None => file_info,
+    };
+
+    let snapshot = file_info.snapshot();
+    tracing::Span::current().record("upload_id", snapshot.id());
+
+    if let Some(hook) = &config.pre_create {
+        let payload = HookPayload::new(PRE_CREATE, file_info.snapshot(), context.as_deref());
+
+        if let Err(rejection) = hook.0.pre_create(&payload).await {
+            return Ok(Response::builder().status(rejection.status).body(Body::from(rejection.message)).unwrap());
+        }
+    }
+
     let file_info = match file_store.create_file(file_info).await {
         Ok(info) => info,
         Err(e) => {
-            println!("Error creating file: {:?}", e);
-            return Ok(Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap());
+            crate::metrics::store_error("create", &e);
+
+            let status = match e {
+                FileStoreError::QuotaExceeded => StatusCode::PAYLOAD_TOO_LARGE,
+                _ => {
+                    tracing::error!(upload_id = snapshot.id(), error = %e, "could not create upload");
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            };
+
+            if let Some(events) = &config.events {
+                events.emit(UploadEventKind::Failed(format!("{:?}", e)), snapshot, context.as_deref());
+            }
+
+            return Ok(Response::builder().status(status).body(Body::empty()).unwrap());
         }
     };
 
+    crate::metrics::upload_created(&file_info);
+
+    if let Some(events) = &config.events {
+        events.emit(UploadEventKind::Created, file_info.snapshot(), context.as_deref());
+    }
+
+    let peer = connect_info.map(|ConnectInfo(addr)| addr.ip());
+    let location = crate::location::upload_location(config, &headers, &uri, peer, file_info.id());
+
     let response = Response::builder()
         .status(StatusCode::CREATED)
-        .header("Location", format!("/{}", file_info.id()))
+        .header("Location", location)
         .body(Body::empty())
         .unwrap();
 
@@ -53,24 +122,20 @@ where
 }
 
 #[async_trait]
-impl<S, B, T> FromRequest<S, B> for CreationRequest<T>
+impl<S, B> FromRequest<S, B> for CreationRequest
 where
      B: Send + 'static,
      S: Send + Sync,
-     T: FileStore + Send + Sync + 'static,
 {
     type Rejection = http::StatusCode;
 
-    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {        
+    async fn from_request(req: Request<B>, _state: &S) -> Result<Self, Self::Rejection> {        
         let headers = req.headers();
         
-        let header_map = TusHeaderMap::from_headers(&headers);
-        let tus_resumable_header = match header_map.resumable {
-            Some(resumable) => resumable,
-            None => {
-                return Err(StatusCode::from_u16(400).unwrap());
-            }
-        };
+        let header_map = TusHeaderMap::from_headers(headers);
+        if header_map.resumable.is_none() {
+            return Err(StatusCode::from_u16(400).unwrap());
+        }
         
         let upload_length = match header_map.upload_length {
             Some(upload_length) => upload_length,
@@ -79,29 +144,66 @@ where
             }
         };
 
-        // TODO add max_size value for AXUM-TUS
         let metadata = match header_map.upload_metadata {
             None => None,
             Some(metadata) if metadata.is_empty() => None,
             Some(metadata) => Some(metadata)
         };
 
-        let fstore = Extension::from_request(req, state).await;
-
-        let Extension(file_store): Extension<Arc<T>> = match fstore {
-            Ok(file_store) => file_store,
-            Err(_) => {
-                return Err(StatusCode::from_u16(500).unwrap());
-            }
-        };
-
-        let creation_values = CreationRequest::<T> {
+        let creation_values = CreationRequest {
             upload_length,
             metadata,
-            file_store
         };
 
         Ok(creation_values)
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::{Method, StatusCode};
+    use tower::ServiceExt;
+
+    use crate::test_util::{creation_request, memory_store, tus_app, tus_request};
+    use crate::{MetadataPolicy, TusConfig};
+
+    #[tokio::test]
+    async fn test_metadata_policy_rejects_creation() {
+        let config = TusConfig::new().with_metadata_policy(MetadataPolicy::new().with_required_keys(&["filename"]));
+        let router = tus_app(memory_store(), config);
+
+        let create = |metadata: &str| {
+            let request = creation_request(4).header("Upload-Metadata", metadata).body(Body::empty()).unwrap();
+            router.clone().oneshot(request)
+        };
+
+        let response = create("filetype aW1hZ2UvcG5n").await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let reason = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(reason, "missing required metadata key `filename`");
+
+        assert_eq!(create("filename YS5wbmc=").await.unwrap().status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_location_under_mount_path() {
+        let router = tus_app(memory_store(), TusConfig::new().with_mount_path("/api/files/"));
+
+        let request = tus_request(Method::POST, "/api/files")
+            .header("Host", "uploads.example.com")
+            .header("Upload-Length", "4")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let location = response.headers()["Location"].to_str().unwrap();
+        let path = location.strip_prefix("http://uploads.example.com").unwrap();
+        assert!(path.starts_with("/api/files/"));
+
+        let request = tus_request(Method::HEAD, path).body(Body::empty()).unwrap();
+        assert_eq!(router.oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+    }
+}
@@ -0,0 +1,313 @@
+use axum::{
+    body::StreamBody,
+    extract::{Extension, Path, State},
+    http::{header, HeaderMap, Response, StatusCode},
+    response::IntoResponse,
+};
+use std::ops::Range;
+use crate::TusState;
+use crate::filesystem::file_store::*;
+
+use super::{authorize, RequestClaims};
+
+/// The outcome of parsing a `Range` header against an upload of a given length.
+#[derive(Debug, PartialEq)]
+enum RequestedRange {
+    Full,
+    Partial(Range<u64>),
+    Unsatisfiable,
+}
+
+pub async fn download_handler<T>(
+    Path(id): Path<String>,
+    State(state): State<TusState<T>>,
+    claims: Option<Extension<RequestClaims>>,
+    headers: HeaderMap,
+) -> Result<axum::response::Response, StatusCode>
+where
+    T: FileStore + Send + Sync + 'static
+{
+    let Some(download) = &state.config.download else {
+        return Err(StatusCode::METHOD_NOT_ALLOWED);
+    };
+
+    tracing::Span::current().record("upload_id", id.as_str());
+
+    let file_store = state.store_of(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let file_info = match file_store.get_file_info(&id).await {
+        Ok(file_info) => file_info,
+        Err(_) => return Err(StatusCode::NOT_FOUND),
+    };
+
+    authorize(claims.as_deref(), &file_info)?;
+
+    let length = *file_info.length();
+    if *file_info.offset() != length {
+        return Err(download.incomplete_status);
+    }
+
+    // Completed uploads never change, so the id and length identify the content.
+    let etag = format!("\"{}-{}\"", file_info.id(), length);
+
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        if if_none_match.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*") {
+            let response = Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(header::ETAG, &etag)
+                .body(axum::body::Empty::new())
+                .unwrap();
+
+            return Ok(response.into_response());
+        }
+    }
+
+    let range = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range) => parse_range(range, length),
+        None => RequestedRange::Full,
+    };
+
+    let mut response = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "no-cache");
+
+    let range = match range {
+        RequestedRange::Full => {
+            response = response.status(StatusCode::OK);
+            0..length
+        },
+        RequestedRange::Partial(range) => {
+            response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", range.start, range.end - 1, length));
+            range
+        },
+        RequestedRange::Unsatisfiable => {
+            let response = Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", length))
+                .body(axum::body::Empty::new())
+                .unwrap();
+
+            return Ok(response.into_response());
+        }
+    };
+
+    let metadata = file_info.metadata().as_ref();
+
+    let filetype = metadata.and_then(|metadata| metadata.try_get_key("filetype"));
+    let file_name = metadata.and_then(|metadata| metadata.try_file_name());
+
+    response = response
+        .header(header::CONTENT_TYPE, download.content_type(filetype.as_deref()))
+        .header(header::CONTENT_LENGTH, range.end - range.start)
+        .header(header::CONTENT_DISPOSITION, content_disposition(file_name.as_deref()))
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+
+    // object stores reject reading an empty range.
+    if range.is_empty() {
+        return Ok(response.body(axum::body::Empty::new()).unwrap().into_response());
+    }
+
+    let stream = match file_store.read_file(&id, range).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            tracing::error!(upload_id = id.as_str(), error = %e, "could not read upload");
+            crate::metrics::store_error("read", &e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Ok(response.body(StreamBody::new(stream)).unwrap().into_response())
+}
+
+/// Parses a single `bytes=` range; anything else (including multiple ranges) serves the whole upload.
+fn parse_range(value: &str, length: u64) -> RequestedRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RequestedRange::Full;
+    };
+
+    if spec.contains(',') {
+        return RequestedRange::Full;
+    }
+
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RequestedRange::Full;
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        ("", "") => return RequestedRange::Full,
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return RequestedRange::Unsatisfiable,
+            Ok(suffix) => length.saturating_sub(suffix)..length,
+            Err(_) => return RequestedRange::Full,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => start..length,
+            Err(_) => return RequestedRange::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => start..(end + 1).min(length),
+            _ => return RequestedRange::Full,
+        },
+    };
+
+    if range.start >= length {
+        return RequestedRange::Unsatisfiable;
+    }
+
+    RequestedRange::Partial(range)
+}
+
+/// Builds an `attachment` disposition from a client supplied file name, dropping any path and
+/// keeping an ASCII fallback next to the percent-encoded UTF-8 name.
+fn content_disposition(file_name: Option<&str>) -> String {
+    let Some(file_name) = file_name else {
+        return "attachment".to_string();
+    };
+
+    let file_name = file_name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+
+    let fallback: String = file_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || " .-_()".contains(c) { c } else { '_' })
+        .collect();
+
+    let encoded: String = file_name
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b".-_".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use http::Request;
+    use tower::ServiceExt;
+
+    use crate::test_util::{create_upload, memory_store, tus_app};
+    use crate::{DownloadConfig, TusConfig};
+
+    async fn get(router: &axum::Router, uri: &str, headers: &[(header::HeaderName, &str)]) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+
+        let response = router.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        (status, headers, bytes.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_download_handler() {
+        let store = memory_store();
+        let id = create_upload(&store, 10).await.id().to_string();
+        store.patch_file(&id, 0, &mut b"01234".to_vec()).await.unwrap();
+
+        let config = TusConfig::new()
+            .with_download(DownloadConfig::new().with_incomplete_status(StatusCode::CONFLICT));
+        let router = tus_app(store.clone(), config);
+        let uri = format!("/{}", id);
+
+        let (status, _, _) = get(&router, &uri, &[]).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        store.patch_file(&id, 5, &mut b"56789".to_vec()).await.unwrap();
+
+        let (status, headers, body) = get(&router, &uri, &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"0123456789");
+        assert_eq!(headers[header::CONTENT_TYPE], "application/octet-stream");
+
+        let (status, headers, body) = get(&router, &uri, &[(header::RANGE, "bytes=2-4")]).await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, b"234");
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes 2-4/10");
+
+        let (status, headers, _) = get(&router, &uri, &[(header::RANGE, "bytes=20-")]).await;
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(headers[header::CONTENT_RANGE], "bytes */10");
+
+        let etag = format!("\"{}-10\"", id);
+        let (status, _, body) = get(&router, &uri, &[(header::IF_NONE_MATCH, &etag)]).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        assert!(body.is_empty());
+
+        let (status, _, _) = get(&router, "/missing", &[]).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_downloads_are_attachments_without_sniffing() {
+        let store = memory_store();
+        // filename "page.html", filetype "text/html"
+        let file_info = store.build_file(4, Some("filename cGFnZS5odG1s,filetype dGV4dC9odG1s")).await.unwrap();
+        let id = store.create_file(file_info).await.unwrap().id().to_string();
+        store.patch_file(&id, 0, &mut b"<h1>".to_vec()).await.unwrap();
+
+        let config = TusConfig::new().with_download(DownloadConfig::new());
+        let router = tus_app(store, config);
+
+        let (status, headers, _) = get(&router, &format!("/{}", id), &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/octet-stream");
+        assert_eq!(headers[header::CONTENT_DISPOSITION], "attachment; filename=\"page.html\"; filename*=UTF-8''page.html");
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    }
+
+    #[test]
+    fn test_content_type_allow_list() {
+        let download = DownloadConfig::new();
+        assert_eq!(download.content_type(Some("image/png")), "image/png");
+        assert_eq!(download.content_type(Some("Text/Plain; charset=utf-8")), "Text/Plain; charset=utf-8");
+        assert_eq!(download.content_type(Some("image/svg+xml")), "application/octet-stream");
+        assert_eq!(download.content_type(None), "application/octet-stream");
+
+        let download = download.with_allowed_content_types(&["image/svg+xml"]);
+        assert_eq!(download.content_type(Some("image/svg+xml")), "image/svg+xml");
+        assert_eq!(download.content_type(Some("image/png")), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-9", 100), RequestedRange::Partial(0..10));
+        assert_eq!(parse_range("bytes=90-", 100), RequestedRange::Partial(90..100));
+        assert_eq!(parse_range("bytes=-10", 100), RequestedRange::Partial(90..100));
+        assert_eq!(parse_range("bytes=95-200", 100), RequestedRange::Partial(95..100));
+        assert_eq!(parse_range("bytes=100-", 100), RequestedRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-1,5-6", 100), RequestedRange::Full);
+        assert_eq!(parse_range("items=0-1", 100), RequestedRange::Full);
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition(Some("../../etc/pass\"wd")),
+            "attachment; filename=\"pass_wd\"; filename*=UTF-8''pass%22wd"
+        );
+        assert_eq!(
+            content_disposition(Some("résumé.pdf")),
+            "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+        );
+        assert_eq!(content_disposition(None), "attachment");
+    }
+}
@@ -1,65 +1,45 @@
-use async_trait::async_trait;
 use axum::{
-    extract::{Extension, Path, FromRequest, FromRequestParts},
+    extract::{Extension, Path, State},
     http::{Response, StatusCode},
     body::Body
 };
-use hyper::{Request, Uri, HeaderMap};
-use std::{io::Cursor, sync::Arc};
 use crate::filesystem::file_store::*;
 
-pub struct InfoRequest<T> {
-    file_store: Arc<T>
-}
+use crate::signing::UploadAccess;
+use crate::TusState;
+
+use super::RequestClaims;
 
 pub async fn file_info_handler<T>(
     Path(id): Path<String>,
-    req: InfoRequest<T>
+    State(state): State<TusState<T>>,
+    access: UploadAccess,
+    claims: Option<Extension<RequestClaims>>,
 ) -> Result<Response<Body>, StatusCode> 
 where 
     T: FileStore + Send + Sync + 'static
 {
-    let file_store = req.file_store;
+    tracing::Span::current().record("upload_id", id.as_str());
+
+    let file_store = state.store_of(&id).ok_or(StatusCode::NOT_FOUND)?;
     match file_store.get_file_info(&id).await {
         Ok(file) => {
-            let response = http::Response::builder()
+            access.authorize(&state.config, claims.as_deref(), &file)?;
+
+            let mut response = http::Response::builder()
                 .status(StatusCode::NO_CONTENT)
                 .header(crate::AxumTusHeaders::UploadLength.name(), file.length_str())
-                .header(crate::AxumTusHeaders::UploadOffset.name(), file.metadata_str())
-                .header(axum::http::header::CACHE_CONTROL, "no-store")
-                .body(Body::empty())
-                .unwrap();
+                .header(crate::AxumTusHeaders::UploadOffset.name(), file.offset().to_string())
+                .header(axum::http::header::CACHE_CONTROL, "no-store");
+
+            if file.metadata().as_ref().is_some_and(|metadata| !metadata.is_empty()) {
+                response = response.header(crate::AxumTusHeaders::UploadMetadata.name(), file.metadata_str());
+            }
+
+            let response = response.body(Body::empty()).unwrap();
             
             Ok(response)
         },
         Err(_) => Err(StatusCode::NOT_FOUND) 
     }     
 }
-
-#[async_trait]
-impl<S, B, T> FromRequest<S, B> for InfoRequest<T>
-where
-     B: Send + 'static,
-     S: Send + Sync,
-     T: FileStore + Send + Sync + 'static,
-{
-    type Rejection = http::StatusCode;
-
-    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {        
-    
-        let fstore = Extension::from_request(req, state).await;
-
-        let Extension(file_store): Extension<Arc<T>> = match fstore {
-            Ok(file_store) => file_store,
-            Err(_) => {
-                return Err(StatusCode::from_u16(500).unwrap());
-            }
-        };
-
-        let info_values = InfoRequest::<T> {
-            file_store
-        };
-
-        Ok(info_values)
-    }
-}
\ No newline at end of file
@@ -1,18 +1,53 @@
 pub mod creation;
+pub mod download;
 pub mod upload_handler;
 pub mod file_info_handler;
 pub mod info;
+pub mod progress;
+pub mod termination;
+pub mod admin;
 
+use axum::http::StatusCode;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use crate::filesystem::file_info::FileInfo;
 
 // Generic AuthClaims trait for JWT claims in our requests
-pub trait AuthClaims {
+pub trait AuthClaims: Send + Sync {
     fn get_user_id(&self) -> &str;
     fn get_expiration(&self) -> usize;
     fn get_subject(&self) -> &str;
+
+    /// The tenant the user belongs to, whose uploads share a quota, see [`QuotaProvider`](crate::QuotaProvider).
+    fn get_tenant_id(&self) -> Option<&str> {
+        None
+    }
+
+    /// Admins can access every upload, regardless of who created it.
+    fn is_admin(&self) -> bool {
+        false
+    }
+}
+
+/// The claims resolved by the auth hook of [`TusConfig`](crate::TusConfig), inserted into the
+/// request extensions so handlers can extract them with `Extension<RequestClaims>`.
+#[derive(Clone)]
+pub struct RequestClaims(pub Arc<dyn AuthClaims>);
+
+/// Checks that the caller may access `file_info`: the auth hook is disabled (no claims), the
+/// caller is an admin, the caller created the upload, or the upload was created without an owner.
+pub(crate) fn authorize<State>(claims: Option<&RequestClaims>, file_info: &FileInfo<State>) -> Result<(), StatusCode> {
+    let Some(RequestClaims(claims)) = claims else {
+        return Ok(());
+    };
+
+    match file_info.owner() {
+        Some(owner) if owner != claims.get_user_id() && !claims.is_admin() => Err(StatusCode::FORBIDDEN),
+        _ => Ok(()),
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExampleJwtClaims {
     pub sub: String,
     // #[serde(deserialize_with = "deserialize_string_from_number")]
@@ -0,0 +1,125 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{stream, Stream, StreamExt};
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+use crate::{TusState, UploadEventKind};
+use crate::filesystem::file_store::*;
+
+use super::{authorize, RequestClaims};
+
+fn progress_event(name: &str, offset: u64, length: u64) -> Result<Event, Infallible> {
+    Ok(Event::default()
+        .event(name)
+        .data(serde_json::json!({ "offset": offset, "length": length }).to_string()))
+}
+
+/// Streams the offset of an upload as Server-Sent Events, see
+/// [`TusConfig::with_progress`](crate::TusConfig::with_progress).
+pub async fn progress_handler<T>(
+    Path(id): Path<String>,
+    State(state): State<TusState<T>>,
+    claims: Option<Extension<RequestClaims>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode>
+where
+    T: FileStore + Send + Sync + 'static
+{
+    tracing::Span::current().record("upload_id", id.as_str());
+
+    let events = state.config.events.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let file_store = state.store_of(&id).ok_or(StatusCode::NOT_FOUND)?;
+
+    // subscribe first, so nothing written while reading the info is missed
+    let receiver = events.subscribe();
+
+    let file_info = file_store.get_file_info(&id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    authorize(claims.as_deref(), &file_info)?;
+
+    let (offset, length) = (*file_info.offset(), *file_info.length());
+    let completed = offset == length;
+
+    let current = match completed {
+        true => progress_event("completed", offset, length),
+        false => progress_event("progress", offset, length),
+    };
+
+    let updates = stream::unfold((!completed).then_some(receiver), move |receiver| {
+        let id = id.clone();
+
+        async move {
+            let mut receiver = receiver?;
+
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(event) if event.file_info.id() == id => event,
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                };
+
+                let length = *event.file_info.length();
+
+                return match event.kind {
+                    UploadEventKind::Progress(offset) => Some((progress_event("progress", offset, length), Some(receiver))),
+                    UploadEventKind::Completed => Some((progress_event("completed", length, length), None)),
+                    UploadEventKind::Terminated | UploadEventKind::Expired => {
+                        Some((progress_event("terminated", *event.file_info.offset(), length), None))
+                    },
+                    UploadEventKind::Created | UploadEventKind::Failed(_) => continue,
+                };
+            }
+        }
+    });
+
+    Ok(Sse::new(stream::once(async move { current }).chain(updates)).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{Body, HttpBody};
+    use http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    use crate::test_util::{create_upload, memory_store, tus_app};
+    use crate::{TusConfig, UploadEventKind, UploadEvents};
+
+    async fn next_event<B>(body: &mut B) -> Option<String>
+    where
+        B: HttpBody<Data = bytes::Bytes> + Unpin,
+        B::Error: std::fmt::Debug,
+    {
+        let data = body.data().await?.unwrap();
+        Some(String::from_utf8(data.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_progress_is_streamed() {
+        let store = memory_store();
+        let file_info = create_upload(&store, 6).await;
+        let id = file_info.id().to_string();
+
+        let events = UploadEvents::new(16);
+        let config = TusConfig::new().with_events(events.clone()).with_progress();
+        let app = tus_app(store, config);
+
+        let request = Request::get(format!("/{}/progress", id)).body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+        let mut body = response.into_body();
+        assert_eq!(next_event(&mut body).await.unwrap(), "event:progress\ndata:{\"length\":6,\"offset\":0}\n\n");
+
+        let mut progress = file_info.clone();
+        progress.set_offset(3).unwrap();
+        events.emit(UploadEventKind::Progress(3), progress, None);
+        assert_eq!(next_event(&mut body).await.unwrap(), "event:progress\ndata:{\"length\":6,\"offset\":3}\n\n");
+
+        events.emit(UploadEventKind::Completed, file_info, None);
+        assert_eq!(next_event(&mut body).await.unwrap(), "event:completed\ndata:{\"length\":6,\"offset\":6}\n\n");
+        assert_eq!(next_event(&mut body).await, None);
+    }
+}
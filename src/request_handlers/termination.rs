@@ -0,0 +1,148 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+};
+use crate::filesystem::{file_info::{Created, FileInfo}, file_store::*};
+use crate::{RequestContext, TusState, UploadEventKind};
+
+use super::{authorize, RequestClaims};
+
+pub async fn termination_handler<T>(
+    Path(id): Path<String>,
+    State(state): State<TusState<T>>,
+    claims: Option<Extension<RequestClaims>>,
+    context: Option<Extension<RequestContext>>,
+) -> StatusCode
+where
+    T: FileStore + Send + Sync + 'static
+{
+    tracing::Span::current().record("upload_id", id.as_str());
+
+    let Some(file_store) = state.store_of(&id) else {
+        return StatusCode::NOT_FOUND;
+    };
+    let file_info = match file_store.get_file_info(&id).await {
+        Ok(file_info) => file_info,
+        Err(_) => return StatusCode::NOT_FOUND,
+    };
+
+    if let Err(status) = authorize(claims.as_deref(), &file_info) {
+        return status;
+    }
+
+    terminate(&state, &file_store, file_info, context.as_deref()).await
+}
+
+/// Deletes `file_info` from `file_store`, recording the termination or the failure in the metrics
+/// and events of `state`.
+pub(crate) async fn terminate<T: FileStore>(
+    state: &TusState<T>,
+    file_store: &T,
+    file_info: FileInfo<Created>,
+    context: Option<&RequestContext>,
+) -> StatusCode {
+    match file_store.delete_file(file_info.id()).await {
+        Ok(_) => {
+            crate::metrics::upload_terminated(&file_info);
+
+            if let Some(events) = &state.config.events {
+                events.emit(UploadEventKind::Terminated, file_info, context);
+            }
+
+            StatusCode::NO_CONTENT
+        },
+        Err(e) => {
+            tracing::error!(upload_id = file_info.id(), error = %e, "could not terminate upload");
+            crate::metrics::store_error("terminate", &e);
+
+            if let Some(events) = &state.config.events {
+                events.emit(UploadEventKind::Failed(format!("{:?}", e)), file_info, context);
+            }
+
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::{request::Parts, Method, StatusCode};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    use crate::test_util::{memory_store, tus_app, tus_request};
+    use crate::{AuthClaims, DownloadConfig, ExampleJwtClaims, FileStore, TusConfig};
+
+    struct AdminClaims;
+
+    impl AuthClaims for AdminClaims {
+        fn get_user_id(&self) -> &str {
+            "admin"
+        }
+
+        fn get_expiration(&self) -> usize {
+            usize::MAX
+        }
+
+        fn get_subject(&self) -> &str {
+            "admin"
+        }
+
+        fn is_admin(&self) -> bool {
+            true
+        }
+    }
+
+    // the test "authenticates" the user named by the x-user header.
+    fn resolve_user(parts: &Parts) -> Option<Arc<dyn AuthClaims>> {
+        let user = parts.headers.get("x-user")?.to_str().ok()?;
+        if user == "admin" {
+            return Some(Arc::new(AdminClaims));
+        }
+
+        Some(Arc::new(ExampleJwtClaims {
+            sub: user.to_string(),
+            user_id: user.to_string(),
+            exp: usize::MAX,
+        }))
+    }
+
+    async fn send(router: &axum::Router, method: Method, uri: &str, user: Option<&str>) -> http::Response<axum::body::BoxBody> {
+        let mut request = tus_request(method, uri).header("Upload-Length", "4");
+
+        if let Some(user) = user {
+            request = request.header("x-user", user);
+        }
+
+        router.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_uploads_are_owned_by_their_creator() {
+        let store = memory_store();
+        let config = TusConfig::new()
+            .with_download(DownloadConfig::new())
+            .with_auth(resolve_user);
+        let router = tus_app(store.clone(), config);
+
+        assert_eq!(send(&router, Method::POST, "/", None).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(send(&router, Method::OPTIONS, "/", None).await.status(), StatusCode::NO_CONTENT);
+
+        let response = send(&router, Method::POST, "/", Some("alice")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let location = response.headers()["Location"].to_str().unwrap().to_string();
+
+        let file_info = store.get_file_info(location.trim_start_matches('/')).await.unwrap();
+        assert_eq!(file_info.owner(), Some("alice"));
+
+        assert_eq!(send(&router, Method::HEAD, &location, Some("bob")).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(send(&router, Method::GET, &location, Some("bob")).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(send(&router, Method::DELETE, &location, Some("bob")).await.status(), StatusCode::FORBIDDEN);
+
+        assert_eq!(send(&router, Method::HEAD, &location, Some("alice")).await.status(), StatusCode::NO_CONTENT);
+        assert_eq!(send(&router, Method::HEAD, &location, Some("admin")).await.status(), StatusCode::NO_CONTENT);
+        assert_eq!(send(&router, Method::DELETE, &location, Some("alice")).await.status(), StatusCode::NO_CONTENT);
+        assert_eq!(send(&router, Method::HEAD, &location, Some("alice")).await.status(), StatusCode::NOT_FOUND);
+    }
+}
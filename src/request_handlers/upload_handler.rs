@@ -1,59 +1,217 @@
 use async_trait::async_trait;
 use axum::{
-    extract::{Extension, Path, FromRequest, FromRequestParts},
+    extract::{Extension, Path, FromRequest, State},
     http::{Response, StatusCode},
-    body::{Body,Full, HttpBody}, response,
+    body::{Body, HttpBody}, response,
 };
 use hyper::{Request};
-use std::{io::Cursor, sync::Arc, result, convert::Infallible};
+use std::convert::Infallible;
 use crate::TusHeaderMap;
 use crate::filesystem::file_store::*;
 
-pub struct UploadRequest<T> {
+use crate::signing::UploadAccess;
+use crate::{RequestContext, TusState, UploadEventKind};
+
+use super::RequestClaims;
+
+pub struct UploadRequest {
     upload_offset: u64,
-    upload_bytes: bytes::Bytes,
-    file_store: Arc<T>
+    content_length: Option<u64>,
+    upload_body: Body,
+}
+
+/// Why writing a PATCH body stopped before reaching its end.
+enum WriteError {
+    Body(hyper::Error),
+    Store(FileStoreError),
+    // the body is longer than what is left of the upload.
+    TooLarge,
+}
+
+async fn write_chunk<T: FileStore>(
+    file_store: &T,
+    id: &str,
+    offset: &mut u64,
+    buffer: &mut Vec<u8>,
+) -> Result<PatchOption, FileStoreError> {
+    let patched = file_store.patch_file(id, *offset, buffer.as_mut_slice()).await?;
+
+    *offset = match &patched {
+        PatchOption::Patched(offset) => *offset,
+        PatchOption::Completed(file_info) => *file_info.length(),
+    };
+    buffer.clear();
+
+    Ok(patched)
+}
+
+/// Writes `body` to the upload in chunks of `chunk_size` bytes as it is received, calling
+/// `on_progress` with the new offset after every chunk. On a broken body the bytes received so
+/// far are still written, so the client can resume from there. A body longer than `remaining`
+/// is rejected as soon as it gets past it, so the chunk completing the upload is only written
+/// once the body ends.
+async fn write_body<T, F>(
+    file_store: &T,
+    id: &str,
+    mut offset: u64,
+    remaining: u64,
+    mut body: Body,
+    chunk_size: usize,
+    mut on_progress: F,
+) -> Result<PatchOption, WriteError>
+where
+    T: FileStore,
+    F: FnMut(u64),
+{
+    let mut buffer = Vec::new();
+    let mut patched = None;
+    let mut received = 0;
+
+    while let Some(data) = body.data().await {
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                if !buffer.is_empty() {
+                    if let Ok(patched) = write_chunk(file_store, id, &mut offset, &mut buffer).await {
+                        on_progress(offset);
+
+                        // every byte of the upload arrived before the body broke
+                        if let PatchOption::Completed(_) = patched {
+                            return Ok(patched);
+                        }
+                    }
+                }
+
+                return Err(WriteError::Body(e));
+            }
+        };
+
+        received += data.len() as u64;
+        if received > remaining {
+            return Err(WriteError::TooLarge);
+        }
+
+        buffer.extend_from_slice(&data);
+
+        if buffer.len() >= chunk_size && received < remaining {
+            patched = Some(write_chunk(file_store, id, &mut offset, &mut buffer).await.map_err(WriteError::Store)?);
+            on_progress(offset);
+        }
+    }
+
+    // the remainder, or an empty body which still has to be checked against the upload
+    if !buffer.is_empty() || patched.is_none() {
+        patched = Some(write_chunk(file_store, id, &mut offset, &mut buffer).await.map_err(WriteError::Store)?);
+        on_progress(offset);
+    }
+
+    Ok(patched.unwrap())
 }
 
 pub async fn upload_handler<T>(
     Path(id): Path<String>,
-    req: UploadRequest<T>,
-    // claims: Arc<dyn super::AuthClaims>,
+    State(state): State<TusState<T>>,
+    access: UploadAccess,
+    claims: Option<Extension<RequestClaims>>,
+    context: Option<Extension<RequestContext>>,
+    req: UploadRequest,
 ) -> Result<impl response::IntoResponse, Infallible> 
 where
     T: FileStore + Send + Sync + 'static
-{    
-    let file_store = req.file_store;
-    let mut bytes_vector = req.upload_bytes.to_vec();
-    let upload_slice: &mut [u8] = bytes_vector.as_mut_slice();
+{
+    let span = tracing::Span::current();
+    span.record("upload_id", id.as_str());
+    span.record("offset", req.upload_offset);
+
+    let config = &state.config;
 
     // if the file doesn't exist, return 404
-    if !file_store.exists(&id).await {
+    let Some(file_store) = state.store_of(&id) else {
         return Ok(Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap());
+    };
+    let file_info = match file_store.get_file_info(&id).await {
+        Ok(file_info) => file_info,
+        Err(_) => return Ok(Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap()),
+    };
+
+    if let Err(status) = access.authorize(config, claims.as_deref(), &file_info) {
+        return Ok(Response::builder().status(status).body(Body::empty()).unwrap());
     }
 
-    let final_offset: Result<u64, FileStoreError> = match file_store.patch_file(&id, req.upload_offset, upload_slice).await {
-        Ok(result) => {
-            let final_offset = match result {
-                PatchOption::Patched(offset) => offset,
-                PatchOption::Completed(file_info) => {
-                    // check auto terminate logic here.
+    if req.upload_offset != *file_info.offset() {
+        crate::metrics::offset_conflict();
 
-                    *file_info.length()
-                }
-            };
+        return Ok(Response::builder()
+            .status(StatusCode::CONFLICT)
+            .header(crate::AxumTusHeaders::UploadOffset.name(), file_info.offset().to_string())
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let remaining = file_info.length().saturating_sub(*file_info.offset());
+    if req.content_length.is_some_and(|length| length > remaining) {
+        return Ok(Response::builder().status(StatusCode::PAYLOAD_TOO_LARGE).body(Body::empty()).unwrap());
+    }
+
+    let mut progress = file_info.snapshot();
+    let mut received_to = req.upload_offset;
+    let on_progress = |offset| {
+        crate::metrics::bytes_received(offset - received_to);
+        received_to = offset;
+
+        if let Some(events) = &config.events {
+            if progress.set_offset(offset).is_ok() {
+                events.emit(UploadEventKind::Progress(offset), progress.snapshot(), context.as_deref());
+            }
+        }
+    };
 
-            Ok(final_offset)
+    let chunk_size = config.patch_chunk_size().max(file_store.min_patch_size());
+    let written = write_body(&*file_store, &id, req.upload_offset, remaining, req.upload_body, chunk_size, on_progress).await;
+
+    let final_offset = match written {
+        Ok(PatchOption::Patched(offset)) => offset,
+        Ok(PatchOption::Completed(completed)) => {
+            // check auto terminate logic here.
+
+            crate::metrics::upload_completed(&completed);
+
+            if let Some(events) = &config.events {
+                events.emit(UploadEventKind::Completed, completed.snapshot(), context.as_deref());
+            }
+
+            *completed.length()
         },
         Err(e) => {
-            println!("Error patching file: {:?}", e);
-            return Ok(Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()).unwrap());
+            let (status, reason) = match e {
+                WriteError::Body(e) => {
+                    tracing::warn!(upload_id = id.as_str(), error = %e, "PATCH body ended early");
+                    (StatusCode::BAD_REQUEST, e.to_string())
+                },
+                WriteError::Store(e) => {
+                    tracing::error!(upload_id = id.as_str(), error = %e, "could not patch upload");
+                    crate::metrics::store_error("patch", &e);
+                    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+                },
+                WriteError::TooLarge => {
+                    tracing::warn!(upload_id = id.as_str(), "PATCH body exceeds the upload length");
+                    (StatusCode::PAYLOAD_TOO_LARGE, "the PATCH body exceeds the upload length".to_string())
+                },
+            };
+
+            if let Some(events) = &config.events {
+                events.emit(UploadEventKind::Failed(reason), file_info, context.as_deref());
+            }
+
+            return Ok(Response::builder().status(status).body(Body::empty()).unwrap());
         }
     };
 
+    span.record("bytes_written", final_offset.saturating_sub(req.upload_offset));
+
     let response = Response::builder()
         .status(StatusCode::NO_CONTENT)
-        .header(crate::AxumTusHeaders::UploadOffset.name(), final_offset.unwrap().to_string())
+        .header(crate::AxumTusHeaders::UploadOffset.name(), final_offset.to_string())
         .body(Body::empty())
         .unwrap();
 
@@ -61,27 +219,21 @@ where
 }
 
 #[async_trait]
-impl<S, B, T> FromRequest<S, B> for UploadRequest<T>
+impl<S> FromRequest<S, Body> for UploadRequest
 where
-     B: axum::body::HttpBody + Unpin + Send + Sync + 'static,
-     B::Data: Send,
      S: Send + Sync,
-     T: FileStore + Send + Sync + 'static
 {
     type Rejection = http::StatusCode;
     
-    async fn from_request(mut req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {        
+    async fn from_request(req: Request<Body>, _state: &S) -> Result<Self, Self::Rejection> {        
         let (parts, body) = req.into_parts();
 
         let headers = parts.headers;
 
         let header_map =  TusHeaderMap::from_headers(&headers);
-        let tus_resumable_header = match header_map.resumable {
-            Some(resumable) => resumable,
-            None => {
-                return Err(StatusCode::from_u16(400).unwrap());
-            }
-        };
+        if header_map.resumable.is_none() {
+            return Err(StatusCode::from_u16(400).unwrap());
+        }
         
         let upload_offset = match header_map.upload_offset {
             Some(upload_offset) => upload_offset,
@@ -90,6 +242,10 @@ where
             }
         };
 
+        let content_length = headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok()?.parse().ok());
+
         match headers.get(http::header::CONTENT_TYPE) {
             Some(content_type) => {
                 if content_type != "application/offset+octet-stream" {
@@ -101,25 +257,10 @@ where
             }
         }
 
-        let bytes_body = match hyper::body::to_bytes(body).await {
-            Ok(bytes) => {
-
-                bytes
-            },
-            Err(_) => return Err(StatusCode::from_u16(400).unwrap())
-        };
-
-        let file_store = match parts.extensions.get::<Extension<Arc<T>>>() {
-            Some(file_store) => Arc::clone(file_store),
-            None => {
-                return Err(StatusCode::from_u16(500).unwrap());
-            }
-        };
-
-        let upload_values = UploadRequest::<T> {
+        let upload_values = UploadRequest {
             upload_offset,
-            upload_bytes: bytes_body,
-            file_store
+            content_length,
+            upload_body: body,
         };
 
         Ok(upload_values)
@@ -128,6 +269,133 @@ where
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::test_util::{create_upload, memory_store, patch_request, tus_app, tus_request};
+    use crate::TusConfig;
+
+    fn chunked_body(chunks: Vec<std::io::Result<&'static str>>) -> Body {
+        Body::wrap_stream(futures::stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn test_body_is_written_in_chunks() {
+        let store = memory_store();
+        let file_info = create_upload(&store, 10).await;
+
+        let mut offsets = Vec::new();
+        let body = chunked_body(vec![Ok("abc"), Ok("def"), Ok("ghij")]);
+        let written = write_body(&store, file_info.id(), 0, 10, body, 4, |offset| offsets.push(offset)).await;
+
+        assert!(matches!(written, Ok(PatchOption::Completed(_))));
+        assert_eq!(offsets, [6, 10]);
+    }
+
+    #[tokio::test]
+    async fn test_broken_body_keeps_the_received_bytes() {
+        let store = memory_store();
+        let file_info = create_upload(&store, 10).await;
+
+        let broken = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        let body = chunked_body(vec![Ok("abc"), Err(broken)]);
+        let written = write_body(&store, file_info.id(), 0, 10, body, 4, |_| {}).await;
+
+        assert!(matches!(written, Err(WriteError::Body(_))));
+        assert_eq!(store.get_file_info(file_info.id()).await.unwrap().offset(), &3);
+    }
+
+    #[tokio::test]
+    async fn test_broken_body_after_the_last_byte_completes_the_upload() {
+        let store = memory_store();
+        let file_info = create_upload(&store, 3).await;
+
+        let broken = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        let body = chunked_body(vec![Ok("abc"), Err(broken)]);
+        let written = write_body(&store, file_info.id(), 0, 3, body, 4, |_| {}).await;
+
+        assert!(matches!(written, Ok(PatchOption::Completed(_))));
+    }
+
+    #[tokio::test]
+    async fn test_body_past_the_upload_length_is_rejected() {
+        let store = memory_store();
+        let file_info = create_upload(&store, 3).await;
+
+        let body = chunked_body(vec![Ok("ab"), Ok("cd")]);
+        let written = write_body(&store, file_info.id(), 0, 3, body, 4, |_| {}).await;
+
+        assert!(matches!(written, Err(WriteError::TooLarge)));
+        assert_eq!(store.get_file_info(file_info.id()).await.unwrap().offset(), &0);
+    }
+
+    #[tokio::test]
+    async fn test_body_past_the_upload_length_at_a_chunk_boundary_is_rejected() {
+        let store = memory_store();
+        let file_info = create_upload(&store, 4).await;
+
+        let body = chunked_body(vec![Ok("abcd"), Ok("e")]);
+        let written = write_body(&store, file_info.id(), 0, 4, body, 4, |_| {}).await;
+
+        // the upload is not completed behind the rejection
+        assert!(matches!(written, Err(WriteError::TooLarge)));
+        assert_eq!(store.get_file_info(file_info.id()).await.unwrap().offset(), &0);
+    }
+
+    #[tokio::test]
+    async fn test_content_length_past_the_upload_length_is_rejected() {
+        let store = memory_store();
+        let file_info = create_upload(&store, 3).await;
+        let app = tus_app(store.clone(), TusConfig::new());
+
+        let mut request = patch_request(file_info.id(), 0, "abcd");
+        request.headers_mut().insert(http::header::CONTENT_LENGTH, 4.into());
+        let response = tower::ServiceExt::oneshot(app, request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(store.get_file_info(file_info.id()).await.unwrap().offset(), &0);
+    }
+
+    #[tokio::test]
+    async fn test_chunks_are_at_least_the_min_patch_size() {
+        let store = memory_store().with_min_part_size(4);
+        let file_info = create_upload(&store, 6).await;
+        let events = crate::UploadEvents::new(16);
+        let mut received = events.subscribe();
+        let app = tus_app(store, TusConfig::new().with_events(events).with_patch_chunk_size(1));
+
+        let request = patch_request(file_info.id(), 0, chunked_body(vec![Ok("ab"), Ok("cd"), Ok("ef")]));
+        let response = tower::ServiceExt::oneshot(app, request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let mut offsets = Vec::new();
+        while let Ok(event) = received.try_recv() {
+            if let UploadEventKind::Progress(offset) = event.kind {
+                offsets.push(offset);
+            }
+        }
+        assert_eq!(offsets, [4, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_patch_in_router_with_state() {
+        #[derive(Clone)]
+        struct AppState;
+
+        let store = memory_store();
+        let file_info = create_upload(&store, 3).await;
+
+        let app = axum::Router::new()
+            .nest("/files", crate::tus_router(TusState::new(store, TusConfig::new())))
+            .with_state(AppState);
+
+        let request = patch_request(&format!("files/{}", file_info.id()), 0, "abc");
+        let response = tower::ServiceExt::oneshot(app.clone(), request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["Upload-Offset"], "3");
+
+        let request = tus_request(http::Method::HEAD, &format!("/files/{}", file_info.id())).body(Body::empty()).unwrap();
+        let response = tower::ServiceExt::oneshot(app, request).await.unwrap();
+        assert_eq!(response.headers()["Upload-Offset"], "3");
+    }
 
     #[tokio::test]
     async fn test_uri_parse() {
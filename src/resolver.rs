@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use crate::{AuthClaims, RequestContext};
+
+/// Separates the key of the store from the rest of the id of uploads created through a
+/// [`StoreResolver`], e.g. `acme_5f0c…`.
+pub const STORE_KEY_SEPARATOR: char = '_';
+
+/// Picks the store of each upload, e.g. a bucket or root directory per tenant, see
+/// [`TusRouterBuilder::with_store_resolver`](crate::TusRouterBuilder::with_store_resolver).
+///
+/// The key of the store is chosen when the upload is created and prefixed to its id, so every
+/// later request on the upload goes to the same store, whoever sends it.
+pub trait StoreResolver<T>: Send + Sync {
+    /// The key of the store new uploads of `request` are created in, or `None` for the default
+    /// store. Keys can't contain [`STORE_KEY_SEPARATOR`].
+    fn resolve(&self, request: &RequestContext, claims: Option<&dyn AuthClaims>) -> Option<String>;
+
+    /// The store of `key`. Uploads whose store isn't found are answered with `404 Not Found`.
+    fn store(&self, key: &str) -> Option<Arc<T>>;
+
+    /// The keys of every store, for the [`admin_router`](crate::admin_router) to list their
+    /// uploads. Without them only the default store is listed.
+    fn keys(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::{Method, Request, StatusCode};
+    use std::collections::HashMap;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_util::{create_upload, creation_request, memory_store, patch_request, tus_request, MemoryStore};
+    use crate::{admin_router, FileStore, TusConfig, TusRouterBuilder, TusState};
+
+    struct TenantStores(HashMap<String, Arc<MemoryStore>>);
+
+    impl StoreResolver<MemoryStore> for TenantStores {
+        fn resolve(&self, request: &RequestContext, _claims: Option<&dyn AuthClaims>) -> Option<String> {
+            Some(request.headers.get("X-Tenant")?.to_str().ok()?.to_string())
+        }
+
+        fn store(&self, key: &str) -> Option<Arc<MemoryStore>> {
+            self.0.get(key).cloned()
+        }
+
+        fn keys(&self) -> Vec<String> {
+            self.0.keys().cloned().collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_uploads_stay_in_their_store() {
+        let default = memory_store();
+        let acme = Arc::new(memory_store());
+        let stores = TenantStores(HashMap::from([("acme".to_string(), Arc::clone(&acme))]));
+
+        let app: axum::Router = TusRouterBuilder::new(default.clone()).with_store_resolver(stores).build();
+
+        let request = creation_request(3).header("X-Tenant", "acme").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let id = acme.list_files().await.unwrap().remove(0).id().to_string();
+        assert!(id.starts_with("acme_"));
+        assert!(default.list_files().await.unwrap().is_empty());
+
+        // later requests find the store from the id alone
+        let request = patch_request(&id, 0, "abc");
+        assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(acme.get_file_info(&id).await.unwrap().offset(), &3);
+
+        let unknown = id.replacen("acme", "globex", 1);
+        let request = tus_request(Method::HEAD, &format!("/{}", unknown)).body(Body::empty()).unwrap();
+        assert_eq!(app.oneshot(request).await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_see_every_store() {
+        let default = memory_store();
+        let acme = Arc::new(memory_store());
+        let stores = TenantStores(HashMap::from([("acme".to_string(), Arc::clone(&acme))]));
+        let state = TusState::new(default.clone(), TusConfig::new()).with_store_resolver(stores);
+
+        create_upload(&default, 3).await;
+        let file_info = acme.build_file(3, None).await.unwrap().with_store_key("acme");
+        acme.create_file(file_info).await.unwrap();
+
+        let admin: axum::Router = admin_router(state, tower::layer::util::Identity::new());
+
+        let request = Request::get("/uploads").body(Body::empty()).unwrap();
+        let response = admin.clone().oneshot(request).await.unwrap();
+        let list: serde_json::Value = serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(list["total"], 2);
+
+        let acme_id = acme.list_files().await.unwrap().remove(0).id().to_string();
+        let request = Request::delete(format!("/uploads/{}", acme_id)).body(Body::empty()).unwrap();
+        assert_eq!(admin.oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert!(acme.list_files().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_list_shared_stores_once() {
+        let state = TusState::new(memory_store(), TusConfig::new());
+        let acme = Arc::new(memory_store());
+        let stores = TenantStores(HashMap::from([
+            ("acme".to_string(), Arc::clone(&acme)),
+            ("acme-eu".to_string(), Arc::clone(&acme)),
+            ("default".to_string(), Arc::clone(&state.file_store)),
+        ]));
+        let state = state.with_store_resolver(stores);
+
+        create_upload(&*state.file_store, 3).await;
+        create_upload(&*acme, 3).await;
+
+        let admin: axum::Router = admin_router(state, tower::layer::util::Identity::new());
+
+        let request = Request::get("/uploads").body(Body::empty()).unwrap();
+        let response = admin.clone().oneshot(request).await.unwrap();
+        let list: serde_json::Value = serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(list["total"], 2);
+
+        let request = Request::get("/stats").body(Body::empty()).unwrap();
+        let response = admin.oneshot(request).await.unwrap();
+        let stats: serde_json::Value = serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(stats["uploads"], 2);
+    }
+}
@@ -0,0 +1,262 @@
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use http::{request::Parts, StatusCode};
+use sha2::Sha256;
+use std::{fmt, sync::Arc};
+use crate::filesystem::file_info::{unix_timestamp, FileInfo};
+use crate::request_handlers::{authorize, RequestClaims};
+use crate::TusConfig;
+
+/// Carries an [`UploadSignature`] when it can't be passed in the query string.
+pub const UPLOAD_SIGNATURE_HEADER: &str = "Upload-Signature";
+
+/// Signs capabilities to resume a single upload, see [`TusConfig::with_signed_uploads`].
+///
+/// A backend creates the upload through its [`FileStore`](crate::FileStore), signs its id, and
+/// hands out the signed URL: HEAD and PATCH on that upload are then accepted without any other
+/// credentials until the signature expires.
+#[derive(Clone)]
+pub struct UploadSigner {
+    secret: Arc<[u8]>,
+}
+
+impl fmt::Debug for UploadSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UploadSigner")
+    }
+}
+
+impl UploadSigner {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    fn mac(&self, id: &str, expires_at: u64, max_size: Option<u64>) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        let max_size = max_size.map(|max_size| max_size.to_string()).unwrap_or_default();
+        mac.update(format!("{}\n{}\n{}", id, expires_at, max_size).as_bytes());
+
+        mac
+    }
+
+    /// Signs access to the upload `id` until `expires_at` (seconds since the unix epoch), only
+    /// for uploads of at most `max_size` bytes when set.
+    pub fn sign(&self, id: &str, expires_at: u64, max_size: Option<u64>) -> UploadSignature {
+        let signature = self.mac(id, expires_at, max_size).finalize().into_bytes();
+
+        UploadSignature {
+            expires_at,
+            max_size,
+            signature: URL_SAFE_NO_PAD.encode(signature),
+        }
+    }
+
+    /// Signs the upload `id` and appends it to `base_url`, e.g. `https://example.com/files`.
+    pub fn signed_url(&self, base_url: &str, id: &str, expires_at: u64, max_size: Option<u64>) -> String {
+        format!("{}/{}?{}", base_url.trim_end_matches('/'), id, self.sign(id, expires_at, max_size))
+    }
+
+    fn verify(&self, id: &str, signature: &UploadSignature) -> bool {
+        let Ok(bytes) = URL_SAFE_NO_PAD.decode(&signature.signature) else {
+            return false;
+        };
+
+        self.mac(id, signature.expires_at, signature.max_size)
+            .verify_slice(&bytes)
+            .is_ok()
+    }
+
+    /// Checks that `signature` was issued for `file_info`, hasn't expired and allows its size.
+    pub(crate) fn authorize<State>(&self, signature: &UploadSignature, file_info: &FileInfo<State>) -> Result<(), StatusCode> {
+        if !self.verify(file_info.id(), signature) || signature.expires_at <= unix_timestamp() {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        if signature.max_size.is_some_and(|max_size| *file_info.length() > max_size) {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        Ok(())
+    }
+}
+
+/// The signed expiry and max size of an upload, encoded as `expires=..&max_size=..&signature=..`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadSignature {
+    expires_at: u64,
+    max_size: Option<u64>,
+    signature: String,
+}
+
+impl UploadSignature {
+    pub fn expires_at(&self) -> u64 {
+        self.expires_at
+    }
+
+    pub fn max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+
+    /// Parses the signature out of a query string or an [`UPLOAD_SIGNATURE_HEADER`] value,
+    /// returning `None` if it doesn't contain one.
+    pub(crate) fn parse(query: &str) -> Option<Result<Self, StatusCode>> {
+        let mut expires_at = None;
+        let mut max_size = None;
+        let mut signature = None;
+
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match key {
+                "expires" => expires_at = Some(value),
+                "max_size" => max_size = Some(value),
+                "signature" => signature = Some(value),
+                _ => {},
+            }
+        }
+
+        let signature = signature?;
+
+        let parsed = (|| {
+            Some(Self {
+                expires_at: expires_at?.parse().ok()?,
+                max_size: match max_size {
+                    Some(max_size) => Some(max_size.parse().ok()?),
+                    None => None,
+                },
+                signature: signature.to_string(),
+            })
+        })();
+
+        Some(parsed.ok_or(StatusCode::FORBIDDEN))
+    }
+
+    /// Whether the request carries a signature, whether valid or not.
+    pub(crate) fn is_present(parts: &Parts) -> bool {
+        parts.headers.contains_key(UPLOAD_SIGNATURE_HEADER)
+            || parts.uri.query().is_some_and(|query| query.split('&').any(|pair| pair.starts_with("signature=")))
+    }
+}
+
+impl fmt::Display for UploadSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expires={}", self.expires_at)?;
+        if let Some(max_size) = self.max_size {
+            write!(f, "&max_size={}", max_size)?;
+        }
+        write!(f, "&signature={}", self.signature)
+    }
+}
+
+/// How a request on an existing upload is authorized: by a signed URL, or by the caller's claims.
+pub(crate) enum UploadAccess {
+    Signed(UploadSignature),
+    Unsigned,
+}
+
+impl UploadAccess {
+    pub(crate) fn authorize<State>(
+        &self,
+        config: &TusConfig,
+        claims: Option<&RequestClaims>,
+        file_info: &FileInfo<State>,
+    ) -> Result<(), StatusCode> {
+        match (self, &config.signer) {
+            (Self::Signed(signature), Some(signer)) => signer.authorize(signature, file_info),
+            (Self::Signed(_), None) => Err(StatusCode::FORBIDDEN),
+            (Self::Unsigned, _) => authorize(claims, file_info),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for UploadAccess
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // a header without a readable signature is rejected rather than ignored, as it already
+        // let the request past authentication
+        let header = parts.headers.get(UPLOAD_SIGNATURE_HEADER).map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(UploadSignature::parse)
+                .unwrap_or(Err(StatusCode::FORBIDDEN))
+        });
+
+        let signature = header.or_else(|| parts.uri.query().and_then(UploadSignature::parse));
+
+        match signature {
+            Some(signature) => Ok(Self::Signed(signature?)),
+            None => Ok(Self::Unsigned),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use http::Method;
+    use tower::ServiceExt;
+
+    use crate::test_util::{create_upload, memory_store, tus_app, tus_request};
+
+    #[test]
+    fn test_signature_round_trip() {
+        let signer = UploadSigner::new(b"secret");
+        let signature = signer.sign("abc", 4102444800, Some(1024));
+
+        let parsed = UploadSignature::parse(&signature.to_string()).unwrap().unwrap();
+        assert_eq!(parsed, signature);
+        assert!(signer.verify("abc", &parsed));
+        assert!(!signer.verify("abd", &parsed));
+        assert!(!UploadSigner::new(b"other").verify("abc", &parsed));
+
+        assert_eq!(UploadSignature::parse("expires=1"), None);
+        assert_eq!(UploadSignature::parse("expires=soon&signature=x"), Some(Err(StatusCode::FORBIDDEN)));
+    }
+
+    #[tokio::test]
+    async fn test_signed_urls_bypass_auth() {
+        let store = memory_store();
+        let id = create_upload(&store, 4).await.id().to_string();
+
+        let signer = UploadSigner::new(b"secret");
+        let config = TusConfig::new()
+            .with_auth(|_| None)
+            .with_signed_uploads(signer.clone());
+        let router = tus_app(store, config);
+
+        let head = |uri: String| {
+            let request = tus_request(Method::HEAD, &uri).body(Body::empty()).unwrap();
+            router.clone().oneshot(request)
+        };
+
+        let expires_at = unix_timestamp() + 60;
+        let signed = signer.signed_url("", &id, expires_at, None);
+        assert_eq!(head(signed).await.unwrap().status(), StatusCode::NO_CONTENT);
+
+        assert_eq!(head(format!("/{}", id)).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        let expired = signer.signed_url("", &id, unix_timestamp() - 1, None);
+        assert_eq!(head(expired).await.unwrap().status(), StatusCode::FORBIDDEN);
+
+        let too_small = signer.signed_url("", &id, expires_at, Some(2));
+        assert_eq!(head(too_small).await.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let other_id = format!("/{}?{}", id, signer.sign("other", expires_at, None));
+        assert_eq!(head(other_id).await.unwrap().status(), StatusCode::FORBIDDEN);
+
+        let request = tus_request(Method::HEAD, &format!("/{}", id))
+            .header(UPLOAD_SIGNATURE_HEADER, "unsigned")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(router.oneshot(request).await.unwrap().status(), StatusCode::FORBIDDEN);
+    }
+}
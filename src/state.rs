@@ -0,0 +1,104 @@
+use axum::extract::FromRef;
+use http::StatusCode;
+use std::sync::Arc;
+
+use crate::{AuthClaims, RequestContext, StoreResolver, TusConfig, STORE_KEY_SEPARATOR};
+
+/// The state of the tus routes, extracted by every handler with `State<TusState<T>>`.
+///
+/// [`tus_router`](crate::tus_router) provides it itself, so the routes can be merged or nested
+/// into a router with any state. To route the handlers in your own router instead, implement
+/// `FromRef<AppState>` for `TusState<T>`.
+pub struct TusState<T> {
+    /// The store of every upload, unless a [`StoreResolver`] picks another one.
+    pub file_store: Arc<T>,
+    pub config: Arc<TusConfig>,
+    resolver: Option<Arc<dyn StoreResolver<T>>>,
+}
+
+impl<T> TusState<T> {
+    pub fn new(file_store: T, config: TusConfig) -> Self {
+        Self {
+            file_store: Arc::new(file_store),
+            config: Arc::new(config),
+            resolver: None,
+        }
+    }
+
+    /// Picks the store of each upload with `resolver`, falling back to the default store.
+    pub fn with_store_resolver<R: StoreResolver<T> + 'static>(mut self, resolver: R) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// The store a new upload of `request` goes to, and the key to prefix its id with.
+    pub(crate) fn resolve(
+        &self,
+        request: Option<&RequestContext>,
+        claims: Option<&dyn AuthClaims>,
+    ) -> Result<(Arc<T>, Option<String>), StatusCode> {
+        let key = match (&self.resolver, request) {
+            (Some(resolver), Some(request)) => resolver.resolve(request, claims),
+            _ => None,
+        };
+
+        let Some(key) = key else {
+            return Ok((Arc::clone(&self.file_store), None));
+        };
+
+        if key.is_empty() || key.contains(STORE_KEY_SEPARATOR) {
+            tracing::error!(key, "the store resolver returned an invalid key");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+
+        match self.resolver.as_ref().and_then(|resolver| resolver.store(&key)) {
+            Some(file_store) => Ok((file_store, Some(key))),
+            None => {
+                tracing::error!(key, "the store resolver has no store for its key");
+                Err(StatusCode::INTERNAL_SERVER_ERROR)
+            },
+        }
+    }
+
+    /// The default store followed by every other store of the resolver, each listed once even if
+    /// several keys map to it.
+    pub(crate) fn stores(&self) -> Vec<Arc<T>> {
+        let resolved = self.resolver.iter().flat_map(|resolver| {
+            resolver.keys().into_iter().filter_map(|key| resolver.store(&key))
+        });
+
+        let mut stores: Vec<Arc<T>> = Vec::new();
+        for file_store in std::iter::once(Arc::clone(&self.file_store)).chain(resolved) {
+            if !stores.iter().any(|listed| Arc::ptr_eq(listed, &file_store)) {
+                stores.push(file_store);
+            }
+        }
+
+        stores
+    }
+
+    /// The store upload `id` was created in, `None` if it is gone.
+    pub(crate) fn store_of(&self, id: &str) -> Option<Arc<T>> {
+        match (&self.resolver, id.split_once(STORE_KEY_SEPARATOR)) {
+            (Some(resolver), Some((key, _))) => resolver.store(key),
+            _ => Some(Arc::clone(&self.file_store)),
+        }
+    }
+}
+
+// derived `Clone` would require `T: Clone`
+impl<T> Clone for TusState<T> {
+    fn clone(&self) -> Self {
+        Self {
+            file_store: Arc::clone(&self.file_store),
+            config: Arc::clone(&self.config),
+            resolver: self.resolver.clone(),
+        }
+    }
+}
+
+impl<T> FromRef<TusState<T>> for Arc<TusConfig> {
+    fn from_ref(state: &TusState<T>) -> Self {
+        Arc::clone(&state.config)
+    }
+}
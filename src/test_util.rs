@@ -0,0 +1,53 @@
+// Helpers shared by the tests of the handlers, hooks and stores.
+
+use axum::{body::Body, Router};
+use http::{header, request::Builder, Request};
+use object_store::memory::InMemory;
+use std::{sync::Arc, time::Duration};
+
+use crate::filesystem::file_info::{Created, FileInfo};
+use crate::{setup_tus_routes_with_config, FileStore, ObjectFileStore, TusConfig};
+
+pub(crate) type MemoryStore = ObjectFileStore<InMemory>;
+
+pub(crate) fn memory_store() -> MemoryStore {
+    ObjectFileStore::new(Arc::new(InMemory::new()))
+}
+
+pub(crate) async fn create_upload<T: FileStore>(store: &T, length: u64) -> FileInfo<Created> {
+    store.create_file(store.build_file(length, None).await.unwrap()).await.unwrap()
+}
+
+/// The tus routes of `store`, at the root of the router.
+pub(crate) fn tus_app<T: FileStore + 'static>(store: T, config: TusConfig) -> Router {
+    setup_tus_routes_with_config(Router::new(), store, config)
+}
+
+/// A tus request, with the `Tus-Resumable` header set.
+pub(crate) fn tus_request(method: http::Method, uri: &str) -> Builder {
+    Request::builder().method(method).uri(uri).header("Tus-Resumable", "1.0.0")
+}
+
+/// A POST creating an upload of `length` bytes.
+pub(crate) fn creation_request(length: u64) -> Builder {
+    tus_request(http::Method::POST, "/").header("Upload-Length", length)
+}
+
+/// A PATCH writing `body` to upload `id` at `offset`.
+pub(crate) fn patch_request(id: &str, offset: u64, body: impl Into<Body>) -> Request<Body> {
+    tus_request(http::Method::PATCH, &format!("/{}", id))
+        .header("Upload-Offset", offset)
+        .header(header::CONTENT_TYPE, "application/offset+octet-stream")
+        .body(body.into())
+        .unwrap()
+}
+
+/// Waits up to two seconds for work running in the background, e.g. delivering events.
+pub(crate) async fn eventually(mut done: impl FnMut() -> bool) {
+    for _ in 0..200 {
+        if done() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
@@ -1,48 +1,47 @@
-use futures::{Future, future::BoxFuture};
-use http::{Request, StatusCode, HeaderValue, Response};
-use axum::body::Body;
-use crate::{FileStore, TusHeaderMap};
-use std::pin::Pin;
+use futures::future::BoxFuture;
+use http::{Request, StatusCode, Response};
+use axum::response::IntoResponse;
+use crate::{CorsConfig, RequestClaims, RequestContext, TusConfig, TusHeaderMap, UploadSignature};
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Instant;
 use tower::{Layer, Service};
+use tracing::{field::Empty, Instrument};
 use crate::AxumTusHeaders;
 
 pub type BoxBody = http_body::combinators::UnsyncBoxBody<bytes::Bytes, axum::Error>;
 
 #[derive(Clone)]
-pub struct TusLayer<T: FileStore + Send + Sync + 'static> {
-    pub file_store: Arc<T>,
+pub struct TusLayer {
+    pub config: Arc<TusConfig>,
 }
 
-impl<S, T> Layer<S> for TusLayer<T>
+impl<S> Layer<S> for TusLayer
 where
     S: Service<http::Request<axum::body::Body>> + Clone + Send + 'static,
-    T: FileStore + Send + Sync + 'static,
     S::Response: Send + 'static,
     S::Error: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
     S::Future: Send + 'static,
 {
-    type Service = TusService<S, T>;
+    type Service = TusService<S>;
 
     fn layer(&self, service: S) -> Self::Service {
         TusService {
             service,
-            file_store: Arc::clone(&self.file_store),
+            config: Arc::clone(&self.config),
         }
     }
 }
 
 #[derive(Clone)]
-pub struct TusService<S, T: FileStore> {
+pub struct TusService<S> {
     service: S,
-    file_store: Arc<T>,
+    config: Arc<TusConfig>,
 }
 
-impl<S, T> Service<Request<axum::body::Body>> for TusService<S, T>
+impl<S> Service<Request<axum::body::Body>> for TusService<S>
 where
     S: Service<Request<axum::body::Body>, Response = Response<BoxBody>> + Send + 'static,
-    T: FileStore + Send + Sync + 'static,
     S::Future: Send + 'static,
 {
     type Response = Response<BoxBody>;
@@ -53,21 +52,201 @@ where
         self.service.poll_ready(cx)
     }
 
-    fn call(&mut self, mut request: http::Request<axum::body::Body>) -> Self::Future {
-        
-        // make filestore usable inside request handlers.
-        request.extensions_mut().insert(Arc::clone(&self.file_store));
-        
-        let fut = self.service.call(request);
+    fn call(&mut self, request: http::Request<axum::body::Body>) -> Self::Future {
+        let (mut parts, body) = request.into_parts();
+        let started = Instant::now();
+
+        // clients which can't send PATCH or DELETE tunnel them through POST, other methods
+        // can be sent as they are and aren't overridden
+        if !self.config.ignore_method_override && parts.method == http::Method::POST {
+            let method = parts
+                .headers
+                .get(AxumTusHeaders::MethodOverride.name())
+                .and_then(|method| http::Method::from_bytes(method.as_bytes()).ok())
+                .filter(|method| *method == http::Method::PATCH || *method == http::Method::DELETE);
+
+            if let Some(method) = method {
+                parts.method = method;
+            }
+        }
+
+        // handlers record the upload they work on and what they wrote
+        let span = tracing::info_span!(
+            "tus_request",
+            method = %parts.method,
+            path = parts.uri.path(),
+            user_id = Empty,
+            upload_id = Empty,
+            offset = Empty,
+            bytes_written = Empty,
+            status = Empty,
+            duration_ms = Empty,
+        );
+
+        // preflights are answered before the tus routes, which only serve discovery on OPTIONS
+        if let Some(cors) = self.config.cors.as_ref().filter(|_| CorsConfig::is_preflight(&parts)) {
+            let response = cors.preflight(&parts);
+            span.record("status", response.status().as_u16());
+            crate::metrics::request(&parts.method, response.status(), started.elapsed());
+
+            return Box::pin(async move { Ok(response) });
+        }
+        let origin = parts.headers.get(http::header::ORIGIN).cloned();
+
+        // resolve the caller, OPTIONS stays public so clients can discover the server, and
+        // signed HEAD/PATCH requests are checked by the handlers against the signed upload.
+        let signed = self.config.signer.is_some()
+            && matches!(parts.method, http::Method::HEAD | http::Method::PATCH)
+            && UploadSignature::is_present(&parts);
+
+        if let Some(auth) = &self.config.auth {
+            match auth.resolve(&parts) {
+                Some(claims) => {
+                    parts.extensions.insert(RequestClaims(claims));
+                },
+                None if parts.method != http::Method::OPTIONS && !signed => {
+                    span.record("status", StatusCode::UNAUTHORIZED.as_u16());
+                    crate::metrics::request(&parts.method, StatusCode::UNAUTHORIZED, started.elapsed());
+                    tracing::debug!(parent: &span, "rejected request without claims");
+
+                    let tus_header_map = TusHeaderMap::for_config(&self.config);
+                    let config = Arc::clone(&self.config);
+                    return Box::pin(async move {
+                        let mut response = StatusCode::UNAUTHORIZED.into_response();
+                        tus_header_map.apply(response.headers_mut());
+                        if let Some(cors) = &config.cors {
+                            cors.apply(origin.as_ref(), response.headers_mut());
+                        }
+
+                        Ok(response)
+                    });
+                },
+                None => {},
+            }
+        }
+
+        let context = RequestContext::new(
+            parts.method.clone(),
+            parts.uri.clone(),
+            parts.headers.clone(),
+            parts.extensions.get(),
+        );
+        if let Some(user_id) = &context.user_id {
+            span.record("user_id", user_id.as_str());
+        }
+        parts.extensions.insert(context);
+
+        let method = parts.method.clone();
+        let tus_header_map = TusHeaderMap::for_config(&self.config);
+        let config = Arc::clone(&self.config);
+        let fut = span.in_scope(|| self.service.call(Request::from_parts(parts, body)));
 
         Box::pin(async move {
             let mut response = fut.await?;
 
-            let tus_header_map = TusHeaderMap::with_tus_version();
-
             tus_header_map.apply(response.headers_mut());
+            if let Some(cors) = &config.cors {
+                cors.apply(origin.as_ref(), response.headers_mut());
+            }
+
+            let span = tracing::Span::current();
+            span.record("status", response.status().as_u16());
+            span.record("duration_ms", started.elapsed().as_millis() as u64);
+            tracing::info!("finished tus request");
+            crate::metrics::request(&method, response.status(), started.elapsed());
 
             Ok(response)
-        })
+        }.instrument(span))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::{Method, StatusCode};
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+    use tower::ServiceExt;
+
+    use crate::test_util::{create_upload, creation_request, memory_store, tus_app, tus_request};
+    use crate::{FileStore, TusConfig};
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_traced() {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = tus_app(memory_store(), TusConfig::new());
+
+        let response = app.oneshot(creation_request(3).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let id = response.headers()["location"].to_str().unwrap().trim_start_matches('/').to_string();
+        let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+
+        let finished = logs.lines().find(|line| line.contains("finished tus request")).unwrap();
+        assert!(finished.contains("method=POST"));
+        assert!(finished.contains(&format!("upload_id=\"{}\"", id)));
+        assert!(finished.contains("status=201"));
+    }
+
+    #[tokio::test]
+    async fn test_method_override() {
+        let store = memory_store();
+        let file_info = create_upload(&store, 3).await;
+        let app = tus_app(store.clone(), TusConfig::new());
+
+        let request = tus_request(Method::POST, &format!("/{}", file_info.id()))
+            .header("X-HTTP-Method-Override", "PATCH")
+            .header("Upload-Offset", "0")
+            .header("Content-Type", "application/offset+octet-stream")
+            .body(Body::from("abc"))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()["Upload-Offset"], "3");
+
+        let delete = || {
+            tus_request(Method::POST, &format!("/{}", file_info.id()))
+                .header("X-HTTP-Method-Override", "DELETE")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let ignoring = tus_app(store.clone(), TusConfig::new().without_method_override());
+        assert_eq!(ignoring.oneshot(delete()).await.unwrap().status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        assert_eq!(app.oneshot(delete()).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert!(!store.exists(file_info.id()).await);
+    }
+
+    #[tokio::test]
+    async fn test_method_override_only_patch_and_delete() {
+        let app = tus_app(memory_store(), TusConfig::new());
+
+        let request = creation_request(3).header("X-HTTP-Method-Override", "GET").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
     }
 }
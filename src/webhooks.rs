@@ -0,0 +1,318 @@
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use http::{header, StatusCode};
+use sha2::Sha256;
+use std::{fmt, sync::Arc, time::Duration};
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+use crate::{HookPayload, HookRejection, PreCreateHook, UploadEvents};
+
+/// The header carrying `sha256=<hex HMAC-SHA256 of the body>` when a secret is configured, see
+/// [`WebhookNotifier::with_secret`].
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "Hook-Signature";
+
+const DEFAULT_EVENT_TYPES: [&str; 5] = ["created", "completed", "terminated", "expired", "failed"];
+
+/// Why a webhook couldn't be delivered.
+#[derive(Debug)]
+pub enum WebhookError {
+    Request(reqwest::Error),
+    /// The receiver answered with a non-2xx status and this body.
+    Status(StatusCode, String),
+}
+
+impl WebhookError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Request(_) => true,
+            Self::Status(status, _) => status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Request(e) => write!(f, "webhook request failed: {}", e),
+            Self::Status(status, _) => write!(f, "webhook responded with {}", status),
+        }
+    }
+}
+
+/// POSTs [`HookPayload`]s as JSON to one or more URLs, like the HTTP hooks of tusd.
+///
+/// Upload events are delivered by [`listen`](WebhookNotifier::listen), and the notifier can
+/// also be used as a blocking [`PreCreateHook`]: a `4xx` response rejects the upload with the
+/// same status and body, while unreachable receivers reject it with `500`.
+///
+/// ```rust,ignore
+/// let events = UploadEvents::new(1024);
+/// let notifier = WebhookNotifier::new("https://hooks.example.com/tus").with_secret(b"hook-secret");
+/// notifier.listen(&events);
+///
+/// let config = TusConfig::new().with_events(events).with_pre_create_hook(notifier);
+/// ```
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    urls: Vec<String>,
+    client: reqwest::Client,
+    secret: Option<Arc<[u8]>>,
+    max_attempts: u32,
+    backoff: Duration,
+    event_types: Vec<String>,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Self {
+        Self {
+            urls: vec![url.to_string()],
+            client: reqwest::Client::new(),
+            secret: None,
+            max_attempts: 3,
+            backoff: Duration::from_millis(500),
+            event_types: DEFAULT_EVENT_TYPES.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    /// Also sends every payload to `url`.
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.urls.push(url.to_string());
+        self
+    }
+
+    /// Signs every payload, see [`WEBHOOK_SIGNATURE_HEADER`].
+    pub fn with_secret(mut self, secret: &[u8]) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    /// Tries each delivery up to `max_attempts` times, waiting `backoff` after the first failure
+    /// and twice as long after each next one (3 attempts from 500ms by default). Only network
+    /// errors, `429` and `5xx` responses are retried.
+    pub fn with_retries(mut self, max_attempts: u32, backoff: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .expect("the TLS backend is available");
+        self
+    }
+
+    /// The [`UploadEventKind::name`](crate::UploadEventKind::name)s delivered by
+    /// [`listen`](WebhookNotifier::listen), every event except `progress` by default.
+    pub fn with_event_types(mut self, event_types: &[&str]) -> Self {
+        self.event_types = event_types.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    /// The value of the [`WEBHOOK_SIGNATURE_HEADER`] for `body`, `None` without a secret.
+    pub fn signature(&self, body: &[u8]) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+        mac.update(body);
+
+        let hex: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        Some(format!("sha256={}", hex))
+    }
+
+    /// Delivers `payload` to every URL, failing with the first error.
+    pub async fn send(&self, payload: &HookPayload) -> Result<(), WebhookError> {
+        let body = serde_json::to_vec(payload).expect("hook payloads serialize to JSON");
+
+        for url in &self.urls {
+            self.post(url, &body).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Delivers the upload events of `events` in the background until they are dropped.
+    pub fn listen(&self, events: &UploadEvents) -> JoinHandle<()> {
+        let notifier = self.clone();
+        let mut receiver = events.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if notifier.event_types.iter().any(|t| t == event.kind.name()) => {
+                        if let Err(e) = notifier.send(&HookPayload::from(&event)).await {
+                            tracing::error!(
+                                event = event.kind.name(),
+                                upload_id = event.file_info.id(),
+                                error = %e,
+                                "could not deliver webhook"
+                            );
+                        }
+                    },
+                    Ok(_) => {},
+                    Err(RecvError::Lagged(missed)) => tracing::warn!(missed, "webhooks missed upload events"),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    async fn post(&self, url: &str, body: &[u8]) -> Result<(), WebhookError> {
+        let mut attempt = 1;
+        let mut backoff = self.backoff;
+
+        loop {
+            match self.post_once(url, body).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.max_attempts || !e.is_retryable() => return Err(e),
+                Err(e) => {
+                    tracing::warn!(url, attempt, error = %e, "retrying webhook");
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                    backoff *= 2;
+                },
+            }
+        }
+    }
+
+    async fn post_once(&self, url: &str, body: &[u8]) -> Result<(), WebhookError> {
+        let mut request = self
+            .client
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_vec());
+
+        if let Some(signature) = self.signature(body) {
+            request = request.header(WEBHOOK_SIGNATURE_HEADER, signature);
+        }
+
+        let response = request.send().await.map_err(WebhookError::Request)?;
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(());
+        }
+
+        Err(WebhookError::Status(status, response.text().await.unwrap_or_default()))
+    }
+}
+
+#[async_trait]
+impl PreCreateHook for WebhookNotifier {
+    async fn pre_create(&self, payload: &HookPayload) -> Result<(), HookRejection> {
+        match self.send(payload).await {
+            Ok(()) => Ok(()),
+            Err(WebhookError::Status(status, body)) if status.is_client_error() => Err(HookRejection::new(status, body)),
+            Err(e) => {
+                tracing::error!(upload_id = payload.upload.id(), error = %e, "could not call the pre-create webhook");
+                Err(HookRejection::new(StatusCode::INTERNAL_SERVER_ERROR, ""))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::Extension, routing::post, Router};
+    use bytes::Bytes;
+    use http::HeaderMap;
+    use std::{
+        collections::VecDeque,
+        net::TcpListener,
+        sync::Mutex,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_util::{create_upload, creation_request, eventually, memory_store, tus_app};
+    use crate::{FileStore, TusConfig};
+
+    /// A stand-in webhook receiver, answering with the queued responses and then `200 OK`.
+    #[derive(Clone, Default)]
+    struct Receiver {
+        requests: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+        responses: Arc<Mutex<VecDeque<(StatusCode, &'static str)>>>,
+    }
+
+    async fn receive(Extension(receiver): Extension<Receiver>, headers: HeaderMap, body: Bytes) -> (StatusCode, &'static str) {
+        receiver.requests.lock().unwrap().push((headers, body));
+        receiver.responses.lock().unwrap().pop_front().unwrap_or((StatusCode::OK, ""))
+    }
+
+    fn spawn_receiver(responses: &[(StatusCode, &'static str)]) -> (Receiver, String) {
+        let receiver = Receiver::default();
+        receiver.responses.lock().unwrap().extend(responses.iter().copied());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let app = Router::new().route("/hook", post(receive)).layer(Extension(receiver.clone()));
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+
+        (receiver, url)
+    }
+
+    fn payload_type(body: &Bytes) -> String {
+        let payload: serde_json::Value = serde_json::from_slice(body).unwrap();
+        payload["type"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_webhooks_are_signed_and_retried() {
+        let (receiver, url) = spawn_receiver(&[(StatusCode::SERVICE_UNAVAILABLE, "")]);
+        let notifier = WebhookNotifier::new(&url)
+            .with_secret(b"hook-secret")
+            .with_retries(3, Duration::from_millis(10));
+
+        let file_info = create_upload(&memory_store(), 3).await;
+        notifier.send(&HookPayload::new("completed", file_info, None)).await.unwrap();
+
+        let requests = receiver.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+
+        let (headers, body) = &requests[1];
+        assert_eq!(payload_type(body), "completed");
+        assert_eq!(headers[WEBHOOK_SIGNATURE_HEADER], notifier.signature(body).unwrap().as_str());
+
+        let other = WebhookNotifier::new(&url).with_secret(b"other-secret");
+        assert_ne!(other.signature(body), notifier.signature(body));
+    }
+
+    #[tokio::test]
+    async fn test_pre_create_webhook_rejects_uploads() {
+        let (_, url) = spawn_receiver(&[(StatusCode::FORBIDDEN, "uploads are closed")]);
+        let notifier = WebhookNotifier::new(&url).with_retries(3, Duration::from_millis(10));
+
+        let store = memory_store();
+        let app = tus_app(store.clone(), TusConfig::new().with_pre_create_hook(notifier));
+
+        let response = app.oneshot(creation_request(3).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, "uploads are closed");
+        assert!(store.list_files().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_events_are_delivered_after_the_pre_create_hook() {
+        let (receiver, url) = spawn_receiver(&[]);
+        let notifier = WebhookNotifier::new(&url);
+
+        let events = UploadEvents::new(16);
+        notifier.listen(&events);
+
+        let app = tus_app(memory_store(), TusConfig::new().with_events(events).with_pre_create_hook(notifier));
+
+        let response = app.oneshot(creation_request(3).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // the created event is delivered in the background
+        eventually(|| receiver.requests.lock().unwrap().len() == 2).await;
+
+        let types: Vec<String> = receiver.requests.lock().unwrap().iter().map(|(_, body)| payload_type(body)).collect();
+        assert_eq!(types, ["pre-create", "created"]);
+    }
+}